@@ -1,5 +1,8 @@
+mod stream;
+
+use std::fmt;
 use std::fs::File;
-use std::io::{self, BufRead};
+use std::io::{self, BufRead, Write};
 use std::path::Path;
 
 fn load_input<P>(filename: P) -> io::Result<Vec<String>>
@@ -13,76 +16,610 @@ where
         .collect())
 }
 
-fn check_line(l: &str) -> usize {
-    let mut stack = Vec::new();
-    let illegal = l.chars().find(|c| {
-        match c {
-            '(' => stack.push(')'),
-            '[' => stack.push(']'),
-            '{' => stack.push('}'),
-            '<' => stack.push('>'),
-            _ => {
-                if Some(*c) != stack.pop() {
-                    return true;
-                }
+// Opening and closing character of every chunk type
+struct PairTable {
+    pairs: Vec<(char, char)>,
+}
+
+impl PairTable {
+    // Pairs as consecutive characters, e.g. "()[]"
+    fn parse(s: &str) -> Result<Self, String> {
+        let chars: Vec<_> = s.chars().collect();
+        if chars.is_empty() || chars.len() % 2 != 0 {
+            return Err(format!("pair table {:?} needs an even number of chars", s));
+        }
+        let pairs: Vec<_> = chars.chunks(2).map(|p| (p[0], p[1])).collect();
+        for (i, c) in chars.iter().enumerate() {
+            if chars[i + 1..].contains(c) {
+                return Err(format!("{:?} is used twice in the pair table", c));
             }
         }
-        false
-    });
-    if let Some(c) = illegal {
-        match c {
-            ')' => 3,
-            ']' => 57,
-            '}' => 1197,
-            '>' => 25137,
-            _ => unreachable!(),
+        Ok(Self { pairs })
+    }
+
+    fn chunks() -> Self {
+        Self::parse("()[]{}<>").unwrap()
+    }
+
+    fn closing(&self, open: char) -> Option<char> {
+        self.pairs.iter().find(|p| p.0 == open).map(|p| p.1)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Syntax {
+    Valid,
+    // column counts from 1, expected is None if there was no open chunk
+    Corrupted {
+        column: usize,
+        expected: Option<char>,
+        found: char,
+    },
+    Incomplete {
+        missing: String,
+    },
+}
+
+impl fmt::Display for Syntax {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Syntax::Valid => write!(f, "valid"),
+            Syntax::Corrupted {
+                column,
+                expected: Some(e),
+                found,
+            } => write!(
+                f,
+                "corrupted at column {}, expected {} but found {}",
+                column, e, found
+            ),
+            Syntax::Corrupted {
+                column,
+                expected: None,
+                found,
+            } => write!(
+                f,
+                "corrupted at column {}, expected no close but found {}",
+                column, found
+            ),
+            Syntax::Incomplete { missing } => write!(f, "incomplete, missing {}", missing),
         }
-    } else {
-        0
     }
 }
 
-fn get_completion_score(c: &char) -> usize {
-    match c {
-        ')' => 1,
-        ']' => 2,
-        '}' => 3,
-        '>' => 4,
-        _ => unreachable!(),
+// Checks a line char by char, so lines don't need to be in memory as a whole
+struct LineChecker<'a> {
+    table: &'a PairTable,
+    stack: Vec<char>,
+    column: usize,
+    corrupted: Option<Syntax>,
+}
+
+impl<'a> LineChecker<'a> {
+    fn new(table: &'a PairTable) -> Self {
+        Self {
+            table,
+            stack: Vec::new(),
+            column: 0,
+            corrupted: None,
+        }
+    }
+
+    // Returns false once the line is corrupted, later chars are ignored
+    fn push(&mut self, c: char) -> bool {
+        if self.corrupted.is_some() {
+            return false;
+        }
+        self.column += 1;
+        if let Some(close) = self.table.closing(c) {
+            self.stack.push(close);
+        } else if self.stack.last() == Some(&c) {
+            self.stack.pop();
+        } else {
+            self.corrupted = Some(Syntax::Corrupted {
+                column: self.column,
+                expected: self.stack.last().copied(),
+                found: c,
+            });
+            return false;
+        }
+        true
+    }
+
+    fn is_empty(&self) -> bool {
+        self.column == 0
+    }
+
+    // Result of the line so far, the checker starts over with a new line
+    fn finish(&mut self) -> Syntax {
+        let res = match self.corrupted.take() {
+            Some(s) => s,
+            None if self.stack.is_empty() => Syntax::Valid,
+            None => Syntax::Incomplete {
+                missing: self.stack.iter().rev().collect(),
+            },
+        };
+        self.stack.clear();
+        self.column = 0;
+        res
     }
 }
 
-fn calc_completion(l: &str) -> Option<usize> {
-    let mut stack = Vec::new();
+fn check_syntax(l: &str, table: &PairTable) -> Syntax {
+    let mut checker = LineChecker::new(table);
     for c in l.chars() {
-        match c {
-            '(' => stack.push(')'),
-            '[' => stack.push(']'),
-            '{' => stack.push('}'),
-            '<' => stack.push('>'),
-            _ => {
-                if Some(c) != stack.pop() {
-                    return None;
+        if !checker.push(c) {
+            break;
+        }
+    }
+    checker.finish()
+}
+
+#[derive(Debug, PartialEq)]
+enum Edit {
+    Substitute {
+        column: usize,
+        found: char,
+        with: char,
+    },
+    Delete {
+        column: usize,
+        found: char,
+    },
+}
+
+impl fmt::Display for Edit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Edit::Substitute {
+                column,
+                found,
+                with,
+            } => write!(f, "replace {} at column {} with {}", found, column, with),
+            Edit::Delete { column, found } => write!(f, "delete {} at column {}", found, column),
+        }
+    }
+}
+
+struct Repair {
+    // columns refer to the original line
+    edits: Vec<Edit>,
+    line: String,
+}
+
+// Steps to rebuild the repaired line from left to right
+enum Rebuild {
+    Balanced(usize, usize),
+    Keep(usize, char),
+    Delete(usize),
+}
+
+// Finds the fewest substitutions and deletions after which the line is only
+// missing closing chars, and completes it. The interval DP is cubic in the
+// line length, so it is meant for lines of ordinary size.
+fn repair_line(l: &str, table: &PairTable) -> Repair {
+    let s: Vec<_> = l.chars().collect();
+    let n = s.len();
+    // cheapest chunk type for an open at a and a close at b
+    let pair = |a: char, b: char| {
+        table
+            .pairs
+            .iter()
+            .map(|p| ((a != p.0) as usize + (b != p.1) as usize, *p))
+            .min_by_key(|c| c.0)
+            .unwrap()
+    };
+    let open_cost = |c: char| table.closing(c).is_none() as usize;
+    // balanced[i][j]: edits to turn s[i..j] into closed chunks
+    let mut balanced = vec![vec![0; n + 1]; n + 1];
+    for len in 1..=n {
+        for i in 0..=n - len {
+            let j = i + len;
+            let mut best = 1 + balanced[i + 1][j];
+            for k in i + 2..=j {
+                best = best.min(pair(s[i], s[k - 1]).0 + balanced[i + 1][k - 1] + balanced[k][j]);
+            }
+            balanced[i][j] = best;
+        }
+    }
+    // prefix[i]: edits to turn s[..i] into closed chunks and still open chunks
+    let mut prefix = vec![0; n + 1];
+    for i in 1..=n {
+        let mut best = prefix[i - 1] + open_cost(s[i - 1]);
+        for k in 0..i {
+            best = best.min(prefix[k] + balanced[k][i]);
+        }
+        prefix[i] = best;
+    }
+
+    // walk the prefix choices backwards, then rebuild the line forwards
+    let mut steps = Vec::new();
+    let mut i = n;
+    while i > 0 {
+        match (0..i).find(|k| prefix[*k] + balanced[*k][i] == prefix[i]) {
+            Some(k) => {
+                steps.push(Rebuild::Balanced(k, i));
+                i = k;
+            }
+            None if open_cost(s[i - 1]) == 0 => {
+                steps.push(Rebuild::Keep(i - 1, s[i - 1]));
+                i -= 1;
+            }
+            None => {
+                // a stray close becomes an open, any open will do
+                steps.push(Rebuild::Keep(i - 1, table.pairs[0].0));
+                i -= 1;
+            }
+        }
+    }
+    let mut line = String::new();
+    let mut edits = Vec::new();
+    while let Some(step) = steps.pop() {
+        match step {
+            Rebuild::Balanced(i, j) if i == j => {}
+            Rebuild::Balanced(i, j) => {
+                let choice = (i + 2..=j).find_map(|k| {
+                    let (cost, p) = pair(s[i], s[k - 1]);
+                    (cost + balanced[i + 1][k - 1] + balanced[k][j] == balanced[i][j])
+                        .then_some((k, p))
+                });
+                match choice {
+                    Some((k, (open, close))) => {
+                        steps.push(Rebuild::Balanced(k, j));
+                        steps.push(Rebuild::Keep(k - 1, close));
+                        steps.push(Rebuild::Balanced(i + 1, k - 1));
+                        steps.push(Rebuild::Keep(i, open));
+                    }
+                    None => {
+                        steps.push(Rebuild::Balanced(i + 1, j));
+                        steps.push(Rebuild::Delete(i));
+                    }
+                }
+            }
+            Rebuild::Keep(i, c) => {
+                if c != s[i] {
+                    edits.push(Edit::Substitute {
+                        column: i + 1,
+                        found: s[i],
+                        with: c,
+                    });
                 }
+                line.push(c);
             }
+            Rebuild::Delete(i) => edits.push(Edit::Delete {
+                column: i + 1,
+                found: s[i],
+            }),
         }
     }
-    Some(
-        stack
-            .iter()
-            .rev()
-            .map(get_completion_score)
-            .fold(0, |c, i| c * 5 + i),
-    )
+    if let Syntax::Incomplete { missing } = check_syntax(&line, table) {
+        line += &missing;
+    }
+    Repair { edits, line }
+}
+
+// Score per closing character, chars missing in the table score 0
+struct ScoreTable {
+    scores: Vec<(char, usize)>,
+}
+
+impl ScoreTable {
+    // One comma separated score per pair of the pair table
+    fn parse(s: &str, table: &PairTable) -> Result<Self, String> {
+        let values = s
+            .split(',')
+            .map(|v| v.trim().parse::<usize>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("score table {:?}: {}", s, e))?;
+        if values.len() != table.pairs.len() {
+            return Err(format!(
+                "score table {:?} needs {} scores",
+                s,
+                table.pairs.len()
+            ));
+        }
+        Ok(Self {
+            scores: table.pairs.iter().map(|p| p.1).zip(values).collect(),
+        })
+    }
+
+    fn error() -> Self {
+        Self {
+            scores: vec![(')', 3), (']', 57), ('}', 1197), ('>', 25137)],
+        }
+    }
+
+    fn completion() -> Self {
+        Self {
+            scores: vec![(')', 1), (']', 2), ('}', 3), ('>', 4)],
+        }
+    }
+
+    fn get(&self, c: char) -> usize {
+        self.scores.iter().find(|s| s.0 == c).map_or(0, |s| s.1)
+    }
+}
+
+fn error_score(s: &Syntax, scores: &ScoreTable) -> usize {
+    match s {
+        Syntax::Corrupted { found, .. } => scores.get(*found),
+        _ => 0,
+    }
+}
+
+// None if the line isn't incomplete or the score overflows
+fn completion_score(s: &Syntax, scores: &ScoreTable) -> Option<usize> {
+    match s {
+        Syntax::Incomplete { missing } => missing
+            .chars()
+            .try_fold(0usize, |c, m| c.checked_mul(5)?.checked_add(scores.get(m))),
+        _ => None,
+    }
+}
+
+struct Args {
+    pairs: PairTable,
+    error: ScoreTable,
+    completion: ScoreTable,
+    verbose: bool,
+    repair: Option<String>,
+    stream: Option<String>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut pairs = PairTable::chunks();
+    let mut error = None;
+    let mut completion = None;
+    let mut verbose = false;
+    let mut repair = None;
+    let mut stream = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--verbose" {
+            verbose = true;
+            continue;
+        }
+        let value = args.next().ok_or(format!("missing value for {}", arg))?;
+        match arg.as_str() {
+            "--pairs" => pairs = PairTable::parse(&value)?,
+            "--error-scores" => error = Some(value),
+            "--completion-scores" => completion = Some(value),
+            "--repair" => repair = Some(value),
+            "--stream" => stream = Some(value),
+            _ => return Err(format!("unknown argument {}", arg)),
+        }
+    }
+    // scores are given in the order of the pairs, so parse them last
+    Ok(Args {
+        error: match error {
+            Some(s) => ScoreTable::parse(&s, &pairs)?,
+            None => ScoreTable::error(),
+        },
+        completion: match completion {
+            Some(s) => ScoreTable::parse(&s, &pairs)?,
+            None => ScoreTable::completion(),
+        },
+        pairs,
+        verbose,
+        repair,
+        stream,
+    })
 }
 
 fn main() {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            println!("{}", e);
+            println!("usage: day10 [--pairs \"()[]{{}}<>\"] [--error-scores 3,57,1197,25137] [--completion-scores 1,2,3,4] [--verbose] [--repair file] [--stream file|-]");
+            return;
+        }
+    };
+    if let Some(path) = &args.stream {
+        let res = if path == "-" {
+            stream::check_stream(io::stdin().lock(), &args)
+        } else {
+            File::open(path).and_then(|f| stream::check_stream(f, &args))
+        };
+        if let Err(e) = res {
+            println!("{}: {}", path, e);
+        }
+        return;
+    }
     let inp = load_input("input.txt").unwrap();
-    let line_check: Vec<_> = inp.iter().map(|f| check_line(f)).collect();
-    let score: usize = line_check.iter().sum();
+    let results: Vec<_> = inp.iter().map(|l| check_syntax(l, &args.pairs)).collect();
+    if args.verbose {
+        for (i, r) in results.iter().enumerate() {
+            println!("line {}: {}", i + 1, r);
+        }
+    }
+    if let Some(path) = &args.repair {
+        let mut out = io::BufWriter::new(File::create(path).unwrap());
+        for (i, l) in inp.iter().enumerate() {
+            let repair = repair_line(l, &args.pairs);
+            if args.verbose {
+                for e in &repair.edits {
+                    println!("line {}: {}", i + 1, e);
+                }
+            }
+            writeln!(out, "{}", repair.line).unwrap();
+        }
+    }
+    let score: usize = results.iter().map(|r| error_score(r, &args.error)).sum();
     println!("error score {}", score);
-    let mut scores: Vec<_> = inp.iter().filter_map(|f| calc_completion(f)).collect();
+    let mut scores: Vec<_> = results
+        .iter()
+        .filter_map(|r| completion_score(r, &args.completion))
+        .collect();
     scores.sort_unstable();
-    let median_score = scores[scores.len() / 2];
-    println!("completion score {}", median_score);
+    match scores.get(scores.len() / 2) {
+        Some(median_score) => println!("completion score {}", median_score),
+        None => println!("no incomplete lines"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_check_syntax() {
+        let table = PairTable::chunks();
+        assert_eq!(
+            check_syntax("{([(<{}[<>[]}>{[]{[(<()>", &table),
+            Syntax::Corrupted {
+                column: 13,
+                expected: Some(']'),
+                found: '}'
+            }
+        );
+        assert_eq!(
+            check_syntax("())", &table),
+            Syntax::Corrupted {
+                column: 3,
+                expected: None,
+                found: ')'
+            }
+        );
+        let s = check_syntax("[({(<(())[]>[[{[]{<()<>>", &table);
+        assert_eq!(
+            s,
+            Syntax::Incomplete {
+                missing: "}}]])})]".to_string()
+            }
+        );
+        assert_eq!(
+            completion_score(&s, &ScoreTable::completion()),
+            Some(288957)
+        );
+        assert_eq!(check_syntax("<([]){()}[{}]>", &table), Syntax::Valid);
+    }
+
+    #[test]
+    fn test_repair() {
+        let table = PairTable::chunks();
+        let r = repair_line("[({(<(())[]>[[{[]{<()<>>", &table);
+        assert!(r.edits.is_empty());
+        assert_eq!(r.line, "[({(<(())[]>[[{[]{<()<>>}}]])})]");
+        // a missing close before the following closes is better deleted
+        let r = repair_line("[(])", &table);
+        assert_eq!(
+            r.edits,
+            vec![Edit::Delete {
+                column: 3,
+                found: ']'
+            }]
+        );
+        assert_eq!(r.line, "[()]");
+        // a stray close is turned into an open
+        let r = repair_line("))", &table);
+        assert_eq!(r.edits.len(), 1);
+        assert_eq!(r.line, "()");
+        for (line, edits) in [
+            ("{([(<{}[<>[]}>{[]{[(<()>", 1),
+            ("(()))[]", 1),
+            ("(]])", 1),
+            ("[))]", 1),
+            ("(][))", 2),
+        ] {
+            let r = repair_line(line, &table);
+            assert_eq!(r.edits.len(), edits, "{}", line);
+            assert_eq!(apply(line, &r.edits, &table), r.line, "{}", line);
+        }
+    }
+
+    // Applies the edits and completes the line
+    fn apply(line: &str, edits: &[Edit], table: &PairTable) -> String {
+        let mut res = String::new();
+        for (i, c) in line.chars().enumerate() {
+            match edits.iter().find(|e| match e {
+                Edit::Substitute { column, .. } | Edit::Delete { column, .. } => *column == i + 1,
+            }) {
+                Some(Edit::Substitute { with, .. }) => res.push(*with),
+                Some(Edit::Delete { .. }) => {}
+                None => res.push(c),
+            }
+        }
+        match check_syntax(&res, table) {
+            Syntax::Valid => res,
+            Syntax::Incomplete { missing } => res + &missing,
+            s => panic!("{} is {}", res, s),
+        }
+    }
+
+    // Fewest edits found by trying all edits breadth first
+    fn brute_force(line: &str, table: &PairTable) -> usize {
+        let chars = "()[]";
+        let mut level = vec![line.to_string()];
+        for edits in 0.. {
+            if level
+                .iter()
+                .any(|l| !matches!(check_syntax(l, table), Syntax::Corrupted { .. }))
+            {
+                return edits;
+            }
+            let mut next = Vec::new();
+            for l in &level {
+                let cs: Vec<_> = l.chars().collect();
+                for i in 0..cs.len() {
+                    let mut deleted = cs.clone();
+                    deleted.remove(i);
+                    next.push(deleted.into_iter().collect());
+                    for c in chars.chars() {
+                        let mut substituted = cs.clone();
+                        substituted[i] = c;
+                        next.push(substituted.into_iter().collect());
+                    }
+                }
+            }
+            next.sort_unstable();
+            next.dedup();
+            level = next;
+        }
+        unreachable!()
+    }
+
+    #[test]
+    fn test_repair_is_minimal() {
+        let table = PairTable::parse("()[]").unwrap();
+        let mut lines = vec![String::new()];
+        for _ in 0..5 {
+            lines = lines
+                .iter()
+                .flat_map(|l| "()[]".chars().map(move |c| format!("{}{}", l, c)))
+                .collect();
+            for line in &lines {
+                let r = repair_line(line, &table);
+                assert_eq!(r.edits.len(), brute_force(line, &table), "{}", line);
+                assert_eq!(apply(line, &r.edits, &table), r.line, "{}", line);
+            }
+        }
+    }
+
+    #[test]
+    fn test_running_median() {
+        let mut median = stream::RunningMedian::default();
+        assert_eq!(median.median(), None);
+        let mut values = Vec::new();
+        for v in [288957, 5566, 1480781, 995444, 294, 7, 7, 12, 100000] {
+            median.push(v);
+            values.push(v);
+            values.sort_unstable();
+            assert_eq!(median.median(), Some(values[values.len() / 2]));
+        }
+    }
+
+    #[test]
+    fn test_custom_pairs() {
+        let table = PairTable::parse("/\\ab").unwrap();
+        assert_eq!(check_syntax("/a/\\b\\", &table), Syntax::Valid);
+        let s = check_syntax("a/b", &table);
+        assert_eq!(
+            error_score(&s, &ScoreTable::parse("1,2", &table).unwrap()),
+            2
+        );
+        assert!(PairTable::parse("(()").is_err());
+        assert!(PairTable::parse("()((").is_err());
+        assert!(ScoreTable::parse("1,2,3", &table).is_err());
+    }
 }
@@ -0,0 +1,113 @@
+use crate::{completion_score, error_score, Args, LineChecker, Syntax};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::io::{self, Read, Write};
+
+// Median of all values so far, for an even count the upper one like the puzzle
+#[derive(Default)]
+pub struct RunningMedian {
+    lower: BinaryHeap<usize>,
+    upper: BinaryHeap<Reverse<usize>>,
+}
+
+impl RunningMedian {
+    pub fn push(&mut self, v: usize) {
+        match self.upper.peek() {
+            Some(Reverse(m)) if v < *m => self.lower.push(v),
+            _ => self.upper.push(Reverse(v)),
+        }
+        // upper keeps the median, so it has one more or the same number of values
+        if self.lower.len() > self.upper.len() {
+            self.upper.push(Reverse(self.lower.pop().unwrap()));
+        } else if self.upper.len() > self.lower.len() + 1 {
+            self.lower.push(self.upper.pop().unwrap().0);
+        }
+    }
+
+    pub fn median(&self) -> Option<usize> {
+        self.upper.peek().map(|m| m.0)
+    }
+}
+
+// Checks the input in fixed size blocks and reports every line as soon as it ends
+pub fn check_stream<R: Read>(mut input: R, args: &Args) -> io::Result<()> {
+    let stdout = io::stdout();
+    let mut out = io::BufWriter::new(stdout.lock());
+    let mut checker = LineChecker::new(&args.pairs);
+    let mut line = 0;
+    let mut error = 0;
+    let mut median = RunningMedian::default();
+    let mut report = |syntax: Syntax, out: &mut io::BufWriter<_>| -> io::Result<()> {
+        line += 1;
+        match &syntax {
+            Syntax::Valid => {}
+            Syntax::Corrupted { .. } => {
+                error += error_score(&syntax, &args.error);
+                writeln!(out, "line {}: {}, error score {}", line, syntax, error)?;
+            }
+            Syntax::Incomplete { missing } => match completion_score(&syntax, &args.completion) {
+                Some(score) => {
+                    median.push(score);
+                    writeln!(
+                        out,
+                        "line {}: incomplete, {} missing, score {}, median {}",
+                        line,
+                        missing.chars().count(),
+                        score,
+                        median.median().unwrap()
+                    )?;
+                }
+                None => writeln!(
+                    out,
+                    "line {}: incomplete, {} missing, score overflows",
+                    line,
+                    missing.chars().count()
+                )?,
+            },
+        }
+        Ok(())
+    };
+    let mut buf = vec![0u8; 1 << 16];
+    // bytes of a char split between two blocks
+    let mut carry = 0;
+    loop {
+        let n = input.read(&mut buf[carry..])?;
+        if n == 0 {
+            break;
+        }
+        let len = carry + n;
+        let text = match std::str::from_utf8(&buf[..len]) {
+            Ok(text) => text,
+            Err(e) if e.error_len().is_none() => {
+                std::str::from_utf8(&buf[..e.valid_up_to()]).unwrap()
+            }
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        };
+        for c in text.chars() {
+            match c {
+                '\n' => report(checker.finish(), &mut out)?,
+                '\r' => {}
+                c => {
+                    checker.push(c);
+                }
+            }
+        }
+        let valid = text.len();
+        buf.copy_within(valid..len, 0);
+        carry = len - valid;
+    }
+    if carry > 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "stream ends inside of a char",
+        ));
+    }
+    if !checker.is_empty() {
+        report(checker.finish(), &mut out)?;
+    }
+    writeln!(out, "error score {}", error)?;
+    match median.median() {
+        Some(m) => writeln!(out, "completion score {}", m),
+        None => writeln!(out, "no incomplete lines"),
+    }
+}
@@ -1,72 +1,202 @@
+use std::collections::HashMap;
+use std::fmt;
 use std::fs::File;
 use std::io::{self, BufRead};
 use std::path::Path;
 
-fn do_round(inp: &[Vec<u32>]) -> (Vec<Vec<u32>>, usize) {
-    let x_m = inp[0].len() as i32;
-    let y_m = inp.len() as i32;
-    // Update all values by 1
-    let mut grid: Vec<Vec<u32>> = inp
-        .iter()
-        .map(|y| y.iter().map(|x| *x + 1).collect())
-        .collect();
-
-    let mut count = 0;
-    loop {
-        let last_count = count;
-        for x in 0..x_m {
-            for y in 0..y_m {
-                let p = &mut grid[y as usize][x as usize];
-                if *p == 10 {
-                    *p = 0;
-                    count += 1;
-                    for (nx, ny) in [
-                        (x, y + 1),
-                        (x + 1, y),
-                        (x + 1, y + 1),
-                        (x, y - 1),
-                        (x - 1, y),
-                        (x - 1, y - 1),
-                        (x + 1, y - 1),
-                        (x - 1, y + 1),
-                    ] {
-                        if nx >= 0 && nx < x_m && ny >= 0 && ny < y_m {
-                            let np = &mut grid[ny as usize][nx as usize];
-                            if *np != 0 && *np != 10 {
-                                *np += 1;
-                            }
+#[derive(Clone, Copy, PartialEq)]
+enum Neighbourhood {
+    // left, right, up and down
+    Orthogonal,
+    // including the diagonals
+    All,
+}
+
+struct Rules {
+    // energy above this flashes
+    threshold: u32,
+    neighbourhood: Neighbourhood,
+    // the grid edges wrap around like on a torus
+    wrap: bool,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self {
+            threshold: 9,
+            neighbourhood: Neighbourhood::All,
+            wrap: false,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum SimError {
+    MaxRounds(usize),
+    // the states repeat without all octopuses flashing together
+    NeverSynchronises(Cycle),
+}
+
+impl fmt::Display for SimError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SimError::MaxRounds(n) => write!(f, "no result after {} rounds", n),
+            SimError::NeverSynchronises(c) => write!(f, "never synchronises, {}", c),
+        }
+    }
+}
+
+// The state after pre_period rounds repeats every period rounds
+#[derive(Debug, PartialEq)]
+struct Cycle {
+    pre_period: usize,
+    period: usize,
+}
+
+impl fmt::Display for Cycle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "cycle of {} rounds after {} rounds",
+            self.period, self.pre_period
+        )
+    }
+}
+
+struct Octopuses {
+    threshold: u32,
+    energy: Vec<u32>,
+    neighbours: Vec<Vec<usize>>,
+    // cells waiting to flash in the current step
+    queue: Vec<usize>,
+}
+
+impl Octopuses {
+    fn new(inp: &[Vec<u32>], rules: &Rules) -> Self {
+        let (w, h) = (inp[0].len() as isize, inp.len() as isize);
+        let offsets: Vec<_> = (-1..=1)
+            .flat_map(|dy| (-1..=1).map(move |dx| (dx, dy)))
+            .filter(|(dx, dy): &(isize, isize)| match rules.neighbourhood {
+                Neighbourhood::Orthogonal => dx.abs() + dy.abs() == 1,
+                Neighbourhood::All => (*dx, *dy) != (0, 0),
+            })
+            .collect();
+        // small wrapped grids would reach the same cell twice or the cell itself
+        let neighbours = (0..w * h)
+            .map(|i| {
+                let (x, y) = (i % w, i / w);
+                let mut res: Vec<_> = offsets
+                    .iter()
+                    .map(|(dx, dy)| (x + dx, y + dy))
+                    .filter_map(|(nx, ny)| {
+                        if rules.wrap {
+                            Some((nx.rem_euclid(w), ny.rem_euclid(h)))
+                        } else {
+                            ((0..w).contains(&nx) && (0..h).contains(&ny)).then_some((nx, ny))
                         }
+                    })
+                    .map(|(nx, ny)| (ny * w + nx) as usize)
+                    .filter(|n| *n != i as usize)
+                    .collect();
+                res.sort_unstable();
+                res.dedup();
+                res
+            })
+            .collect();
+        Self {
+            threshold: rules.threshold,
+            energy: inp.iter().flatten().copied().collect(),
+            neighbours,
+            queue: Vec::new(),
+        }
+    }
+
+    // Advances one round in place, returns the number of flashes
+    fn step(&mut self) -> usize {
+        for (i, e) in self.energy.iter_mut().enumerate() {
+            *e += 1;
+            if *e > self.threshold {
+                self.queue.push(i);
+            }
+        }
+        let mut count = 0;
+        while let Some(i) = self.queue.pop() {
+            count += 1;
+            for n in &self.neighbours[i] {
+                // a cell is queued when it crosses the threshold, so it flashes only once
+                let e = &mut self.energy[*n];
+                if *e <= self.threshold {
+                    *e += 1;
+                    if *e > self.threshold {
+                        self.queue.push(*n);
                     }
                 }
             }
         }
-        if last_count == count {
-            break;
+        let threshold = self.threshold;
+        for e in self.energy.iter_mut().filter(|e| **e > threshold) {
+            *e = 0;
         }
+        count
+    }
+
+    fn all_flashed(&self) -> bool {
+        self.energy.iter().all(|e| *e == 0)
     }
-    (grid, count)
 }
 
-fn simulate(inp: &[Vec<u32>], rounds: usize) -> usize {
-    (0..rounds)
-        .fold((inp.to_vec(), 0), |(last, flashes), _| {
-            let mut res = do_round(&last);
-            res.1 += flashes;
-            res
-        })
-        .1
+fn simulate(inp: &[Vec<u32>], rules: &Rules, rounds: usize) -> usize {
+    let mut octopuses = Octopuses::new(inp, rules);
+    (0..rounds).map(|_| octopuses.step()).sum()
+}
+
+// Remembers the round of every state to notice when the simulation repeats
+struct History {
+    seen: HashMap<Vec<u32>, usize>,
 }
 
-fn check_for_flash(inp: &[Vec<u32>]) -> usize {
-    let mut grid = (*inp).to_vec();
-    let mut round = 0;
-    loop {
-        grid = do_round(&grid).0;
-        round += 1;
-        if grid.iter().all(|y| y.iter().all(|x| *x == 0)) {
-            break round;
+impl History {
+    fn new(octopuses: &Octopuses) -> Self {
+        Self {
+            seen: HashMap::from([(octopuses.energy.clone(), 0)]),
         }
     }
+
+    fn record(&mut self, octopuses: &Octopuses, round: usize) -> Option<Cycle> {
+        self.seen
+            .insert(octopuses.energy.clone(), round)
+            .map(|first| Cycle {
+                pre_period: first,
+                period: round - first,
+            })
+    }
+}
+
+fn check_for_flash(inp: &[Vec<u32>], rules: &Rules, max_rounds: usize) -> Result<usize, SimError> {
+    let mut octopuses = Octopuses::new(inp, rules);
+    let mut history = History::new(&octopuses);
+    for round in 1..=max_rounds {
+        octopuses.step();
+        if octopuses.all_flashed() {
+            return Ok(round);
+        }
+        if let Some(cycle) = history.record(&octopuses, round) {
+            return Err(SimError::NeverSynchronises(cycle));
+        }
+    }
+    Err(SimError::MaxRounds(max_rounds))
+}
+
+fn find_cycle(inp: &[Vec<u32>], rules: &Rules, max_rounds: usize) -> Result<Cycle, SimError> {
+    let mut octopuses = Octopuses::new(inp, rules);
+    let mut history = History::new(&octopuses);
+    for round in 1..=max_rounds {
+        octopuses.step();
+        if let Some(cycle) = history.record(&octopuses, round) {
+            return Ok(cycle);
+        }
+    }
+    Err(SimError::MaxRounds(max_rounds))
 }
 
 fn load_input<P>(filename: P) -> io::Result<Vec<Vec<u32>>>
@@ -85,12 +215,132 @@ where
         .collect())
 }
 
+struct Args {
+    rules: Rules,
+    max_rounds: usize,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut res = Args {
+        rules: Rules::default(),
+        max_rounds: 10000,
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--orthogonal" => res.rules.neighbourhood = Neighbourhood::Orthogonal,
+            "--wrap" => res.rules.wrap = true,
+            "--threshold" | "--max-rounds" => {
+                let value = args.next().ok_or(format!("missing value for {}", arg))?;
+                let n = value
+                    .parse::<usize>()
+                    .map_err(|e| format!("{}: {}", arg, e))?;
+                if arg == "--threshold" {
+                    res.rules.threshold = n as u32;
+                } else {
+                    res.max_rounds = n;
+                }
+            }
+            _ => return Err(format!("unknown argument {}", arg)),
+        }
+    }
+    Ok(res)
+}
+
 fn main() {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            println!("{}", e);
+            println!("usage: day11 [--threshold n] [--orthogonal] [--wrap] [--max-rounds n]");
+            return;
+        }
+    };
+    let rules = &args.rules;
     let inp = load_input("input.txt").unwrap();
-    let flashes = simulate(&inp, 10);
+    let flashes = simulate(&inp, rules, 10);
     println!("After 10 round {}", flashes);
-    let flashes = simulate(&inp, 100);
+    let flashes = simulate(&inp, rules, 100);
     println!("After 100 round {}", flashes);
-    let all_flash = check_for_flash(&inp);
-    println!("All flash at round {}", all_flash);
+    match check_for_flash(&inp, rules, args.max_rounds) {
+        Ok(all_flash) => println!("All flash at round {}", all_flash),
+        Err(e) => println!("All flash: {}", e),
+    }
+    match find_cycle(&inp, rules, args.max_rounds) {
+        Ok(cycle) => println!("State {}", cycle),
+        Err(e) => println!("State: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn example() -> Vec<Vec<u32>> {
+        [
+            "5483143223",
+            "2745854711",
+            "5264556173",
+            "6141336146",
+            "6357385478",
+            "4167524645",
+            "2176841721",
+            "6882881134",
+            "4846848554",
+            "5283751526",
+        ]
+        .iter()
+        .map(|l| l.chars().map(|c| c.to_digit(10).unwrap()).collect())
+        .collect()
+    }
+
+    #[test]
+    fn test_example() {
+        let inp = example();
+        let rules = Rules::default();
+        assert_eq!(simulate(&inp, &rules, 10), 204);
+        assert_eq!(simulate(&inp, &rules, 100), 1656);
+        assert_eq!(check_for_flash(&inp, &rules, 1000), Ok(195));
+        assert_eq!(
+            find_cycle(&inp, &rules, 1000),
+            Ok(Cycle {
+                pre_period: 195,
+                period: 10
+            })
+        );
+        assert_eq!(
+            check_for_flash(&inp, &rules, 100),
+            Err(SimError::MaxRounds(100))
+        );
+    }
+
+    #[test]
+    fn test_rules() {
+        // the two octopuses keep charging each other out of sync
+        let inp = vec![vec![0, 2]];
+        let rules = Rules {
+            threshold: 3,
+            neighbourhood: Neighbourhood::Orthogonal,
+            wrap: false,
+        };
+        let res = check_for_flash(&inp, &rules, 1000);
+        assert_eq!(
+            res,
+            Err(SimError::NeverSynchronises(Cycle {
+                pre_period: 0,
+                period: 3
+            }))
+        );
+        // on a torus every cell has the same neighbours, so a flat grid stays in sync
+        let inp = vec![vec![5; 4]; 3];
+        let rules = Rules {
+            wrap: true,
+            ..Rules::default()
+        };
+        assert_eq!(simulate(&inp, &rules, 5), 12);
+        assert_eq!(check_for_flash(&inp, &rules, 1000), Ok(5));
+        // in a 2x2 torus the wrapped neighbours are the same cells
+        let o = Octopuses::new(&[vec![0, 0], vec![0, 0]], &rules);
+        assert_eq!(o.neighbours[0], vec![1, 2, 3]);
+    }
 }
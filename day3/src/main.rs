@@ -1,71 +1,157 @@
-use std::convert::TryInto;
+use std::fmt;
 use std::fs::File;
 use std::io::{self, BufRead};
 use std::path::Path;
 
-fn calc_bitcount(lines: &[String]) -> Vec<i32> {
-    let line_sz = lines[0].len();
-    let mut res = vec![0; line_sz];
-    for line in lines {
-        for (x, c) in line.chars().enumerate() {
-            res[x] += if c == '1' { 1 } else { 0 };
+const MAX_WIDTH: usize = 128;
+
+struct DiagnosticReport {
+    width: usize,
+    words: Vec<u128>,
+}
+
+impl DiagnosticReport {
+    fn parse(lines: &[String], width: usize) -> Self {
+        assert!(
+            width > 0 && width <= MAX_WIDTH,
+            "width {} not in 1..={}",
+            width,
+            MAX_WIDTH
+        );
+        let words = lines
+            .iter()
+            .filter(|l| !l.is_empty())
+            .map(|l| {
+                assert_eq!(l.len(), width, "line {} has wrong width", l);
+                l.bytes().fold(0u128, |w, b| match b {
+                    b'0' => w << 1,
+                    b'1' => (w << 1) | 1,
+                    _ => panic!("invalid char {} in line {}", b as char, l),
+                })
+            })
+            .collect();
+        Self { width, words }
+    }
+
+    fn mask(&self) -> u128 {
+        if self.width == MAX_WIDTH {
+            u128::MAX
+        } else {
+            (1 << self.width) - 1
+        }
+    }
+
+    // bitcount[i] is the number of words with bit i (lsb = 0) set
+    fn calc_bitcount(&self) -> Vec<usize> {
+        let mut res = vec![0; self.width];
+        for word in &self.words {
+            let mut w = *word;
+            while w != 0 {
+                res[w.trailing_zeros() as usize] += 1;
+                w &= w - 1;
+            }
         }
+        res
+    }
+
+    fn calc_gamma_epsilon(&self) -> (u128, u128) {
+        let gamma = self
+            .calc_bitcount()
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| **c * 2 > self.words.len())
+            .fold(0u128, |g, (i, _)| g | (1 << i));
+        (gamma, !gamma & self.mask())
+    }
+
+    fn calc_consumption(&self) -> Option<u128> {
+        let (gamma, epsilon) = self.calc_gamma_epsilon();
+        gamma.checked_mul(epsilon)
+    }
+
+    fn calc_life_support(&self) -> Result<(u128, u128), FilterError> {
+        let oxygen = filter_by_bit_criteria(
+            &self.words,
+            self.width,
+            BitCriteria::MostCommon,
+            TieBreak::Ones,
+        )?;
+        let co2 = filter_by_bit_criteria(
+            &self.words,
+            self.width,
+            BitCriteria::LeastCommon,
+            TieBreak::Zeros,
+        )?;
+        Ok((oxygen, co2))
     }
-    res
 }
 
-fn calc_consumption(lines: &[String], bitcount: &[i32]) -> i32 {
-    let msb = (lines.len() / 2).try_into().unwrap();
-    let mut gamma = 0;
-    let mut epsilion = 0;
-    for (i, v) in bitcount.iter().rev().enumerate() {
-        if v > &msb {
-            gamma += 1 << i;
-        } else {
-            epsilion += 1 << i;
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum BitCriteria {
+    MostCommon,
+    LeastCommon,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum TieBreak {
+    Ones,
+    Zeros,
+}
+
+#[derive(Debug, PartialEq)]
+enum FilterError {
+    InvalidWidth(usize),
+    Empty { bit: usize },
+    Ambiguous { remaining: usize },
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FilterError::InvalidWidth(w) => write!(f, "width {} not in 1..={}", w, MAX_WIDTH),
+            FilterError::Empty { bit } => write!(f, "no value left after filtering bit {}", bit),
+            FilterError::Ambiguous { remaining } => {
+                write!(f, "{} different values left after last bit", remaining)
+            }
         }
     }
-    gamma * epsilion
 }
 
-fn get_life_value(lines: &[String], line_sz: usize, x: usize, neg: bool) -> i32 {
-    let (c_p, c_n) = if neg { ('0', '1') } else { ('1', '0') };
-    if x > line_sz {
-        0
-    } else {
-        let s: usize = lines
-            .iter()
-            .map(|f| {
-                if f.chars().nth(x).unwrap() == '1' {
-                    1
-                } else {
-                    0
-                }
-            })
-            .sum();
-        let c = if s >= ((lines.len() + 1) / 2) {
-            c_p
+// Filters words from the msb (bit width - 1) down to bit 0 until one value is left
+fn filter_by_bit_criteria(
+    words: &[u128],
+    width: usize,
+    criteria: BitCriteria,
+    tie: TieBreak,
+) -> Result<u128, FilterError> {
+    if width == 0 || width > MAX_WIDTH {
+        return Err(FilterError::InvalidWidth(width));
+    }
+    let mut words = words.to_vec();
+    for bit in (0..width).rev() {
+        if words.len() <= 1 {
+            break;
+        }
+        let m = 1u128 << bit;
+        let ones = words.iter().filter(|w| **w & m != 0).count();
+        let zeros = words.len() - ones;
+        let keep_ones = if ones == zeros {
+            tie == TieBreak::Ones
         } else {
-            c_n
+            (ones > zeros) == (criteria == BitCriteria::MostCommon)
         };
-        let res: Vec<_> = lines
-            .iter()
-            .filter(|l| l.chars().nth(x).unwrap() == c)
-            .map(String::clone)
-            .collect();
-        if res.len() == 1 {
-            i32::from_str_radix(&res[0], 2).unwrap()
-        } else {
-            get_life_value(&res, line_sz, x + 1, neg)
+        words.retain(|w| (w & m != 0) == keep_ones);
+        if words.is_empty() {
+            return Err(FilterError::Empty { bit });
         }
     }
-}
-
-fn calc_life_support(lines: &[String]) -> i32 {
-    let oxygen = get_life_value(lines, lines.len(), 0, true);
-    let c02 = get_life_value(lines, lines.len(), 0, false);
-    println!("{}, {}", oxygen, c02);
-    c02 * oxygen
+    words.sort_unstable();
+    words.dedup();
+    match words.len() {
+        0 => Err(FilterError::Empty { bit: 0 }),
+        1 => Ok(words[0]),
+        remaining => Err(FilterError::Ambiguous { remaining }),
+    }
 }
 
 fn load_file<P>(filename: P) -> io::Result<Vec<String>>
@@ -81,7 +167,88 @@ where
 
 fn main() {
     let input = load_file("input.txt").unwrap();
-    let bitcount = calc_bitcount(&input);
-    println!("Power consumption {}", calc_consumption(&input, &bitcount));
-    println!("oxygen  rating {}", calc_life_support(&input));
+    let report = DiagnosticReport::parse(&input, input[0].len());
+    match report.calc_consumption() {
+        Some(p) => println!("Power consumption {}", p),
+        None => {
+            let (gamma, epsilon) = report.calc_gamma_epsilon();
+            println!(
+                "Power consumption overflows (gamma {}, epsilon {})",
+                gamma, epsilon
+            );
+        }
+    }
+    match report.calc_life_support() {
+        Ok((oxygen, co2)) => {
+            println!("oxygen {}, co2 {}", oxygen, co2);
+            match oxygen.checked_mul(co2) {
+                Some(r) => println!("life support rating {}", r),
+                None => println!("life support rating overflows"),
+            }
+        }
+        Err(e) => println!("life support rating: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn example() -> Vec<String> {
+        [
+            "00100", "11110", "10110", "10111", "10101", "01111", "00111", "11100", "10000",
+            "11001", "00010", "01010",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect()
+    }
+
+    #[test]
+    fn test_example() {
+        let report = DiagnosticReport::parse(&example(), 5);
+        assert_eq!(report.calc_gamma_epsilon(), (22, 9));
+        assert_eq!(report.calc_consumption(), Some(198));
+        assert_eq!(report.calc_life_support(), Ok((23, 10)));
+    }
+
+    #[test]
+    fn test_filter_policies() {
+        let words = [0b00, 0b01, 0b10, 0b11];
+        let f = |c, t| filter_by_bit_criteria(&words, 2, c, t);
+        assert_eq!(f(BitCriteria::MostCommon, TieBreak::Ones), Ok(0b11));
+        assert_eq!(f(BitCriteria::LeastCommon, TieBreak::Zeros), Ok(0b00));
+        assert_eq!(
+            filter_by_bit_criteria(&words, 1, BitCriteria::MostCommon, TieBreak::Ones),
+            Err(FilterError::Ambiguous { remaining: 2 })
+        );
+        assert_eq!(
+            filter_by_bit_criteria(
+                &[0b00, 0b10, 0b11],
+                2,
+                BitCriteria::LeastCommon,
+                TieBreak::Zeros
+            ),
+            Ok(0b00)
+        );
+        assert_eq!(
+            filter_by_bit_criteria(&[0b10, 0b11], 2, BitCriteria::LeastCommon, TieBreak::Zeros),
+            Err(FilterError::Empty { bit: 1 })
+        );
+        assert_eq!(
+            filter_by_bit_criteria(&words, 129, BitCriteria::MostCommon, TieBreak::Ones),
+            Err(FilterError::InvalidWidth(129))
+        );
+    }
+
+    #[test]
+    fn test_128_bit() {
+        let lines: Vec<String> = example().iter().map(|l| l.repeat(25) + "101").collect();
+        let report = DiagnosticReport::parse(&lines, 128);
+        let (gamma, epsilon) = report.calc_gamma_epsilon();
+        assert_eq!(gamma >> 123, 22);
+        assert_eq!(gamma & 0b111, 0b101);
+        assert_eq!(gamma ^ epsilon, u128::MAX);
+        assert_eq!(report.calc_consumption(), None);
+    }
 }
@@ -1,7 +1,10 @@
-use std::fmt::Debug;
+mod montecarlo;
+use std::collections::HashMap;
+use std::fmt::{self, Debug};
 use std::fs::File;
-use std::io::{self, BufRead};
+use std::io::{self, BufRead, Write};
 use std::path::Path;
+use std::str::FromStr;
 
 fn load_file<P>(filename: P) -> io::Result<Vec<String>>
 where
@@ -16,79 +19,374 @@ where
 
 #[derive(Debug)]
 struct Board {
-    arr: [[i32; 5]; 5],
+    rows: usize,
+    cols: usize,
+    cells: Vec<i32>,
     no: usize,
 }
 
 impl Board {
     fn from_vec(no: usize, numbers: &[Vec<i32>]) -> Self {
-        let mut b = Self {
-            arr: [[0; 5]; 5],
+        let rows = numbers.len();
+        let cols = numbers.first().map_or(0, Vec::len);
+        assert!(
+            numbers.iter().all(|r| r.len() == cols),
+            "board {} has rows of different length",
+            no
+        );
+        Self {
+            rows,
+            cols,
+            cells: numbers.concat(),
             no,
-        };
-        for (x, nos) in numbers.iter().enumerate() {
-            for (y, n) in nos.iter().enumerate() {
-                b.arr[x][y] = *n;
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum WinRule {
+    Rows,
+    Columns,
+    Diagonals,
+    FourCorners,
+    FullCard,
+}
+
+impl FromStr for WinRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rows" => Ok(WinRule::Rows),
+            "columns" => Ok(WinRule::Columns),
+            "diagonals" => Ok(WinRule::Diagonals),
+            "corners" => Ok(WinRule::FourCorners),
+            "full" => Ok(WinRule::FullCard),
+            _ => Err(format!("unknown win rule {}", s)),
+        }
+    }
+}
+
+impl WinRule {
+    // Cell groups of a rows x cols board that complete this rule
+    fn patterns(&self, rows: usize, cols: usize) -> Vec<Vec<usize>> {
+        match self {
+            WinRule::Rows => (0..rows)
+                .map(|x| (0..cols).map(|y| x * cols + y).collect())
+                .collect(),
+            WinRule::Columns => (0..cols)
+                .map(|y| (0..rows).map(|x| x * cols + y).collect())
+                .collect(),
+            WinRule::Diagonals if rows == cols && rows > 0 => {
+                let mut diagonals = vec![
+                    (0..rows).map(|x| x * cols + x).collect(),
+                    (0..rows)
+                        .map(|x| x * cols + cols - 1 - x)
+                        .collect::<Vec<_>>(),
+                ];
+                // both diagonals of a 1x1 board are the same cell
+                diagonals.dedup();
+                diagonals
+            }
+            WinRule::Diagonals => Vec::new(),
+            WinRule::FourCorners if rows > 0 && cols > 0 => {
+                let mut corners = vec![0, cols - 1, (rows - 1) * cols, rows * cols - 1];
+                corners.sort_unstable();
+                corners.dedup();
+                vec![corners]
             }
+            WinRule::FourCorners => Vec::new(),
+            WinRule::FullCard => vec![(0..rows * cols).collect()],
         }
-        b
     }
+}
 
-    fn calc_score(&self, number: &[i32]) -> i32 {
-        let sum: i32 = self
-            .arr
-            .iter()
-            .map(|line| line.iter().filter(|x| !number.contains(x)).sum::<i32>())
-            .sum();
-        sum * number.last().unwrap()
+// Scores a board that just won given its marked cells and the last drawn number
+type ScoreFn = fn(&Board, &[bool], i32) -> i32;
+
+fn score_unmarked_sum(board: &Board, marked: &[bool], number: i32) -> i32 {
+    let sum: i32 = board
+        .cells
+        .iter()
+        .zip(marked)
+        .filter(|(_, m)| !**m)
+        .map(|(n, _)| n)
+        .sum();
+    sum * number
+}
+
+fn score_marked_sum(board: &Board, marked: &[bool], number: i32) -> i32 {
+    let sum: i32 = board
+        .cells
+        .iter()
+        .zip(marked)
+        .filter(|(_, m)| **m)
+        .map(|(n, _)| n)
+        .sum();
+    sum * number
+}
+
+fn parse_score_fn(s: &str) -> Result<ScoreFn, String> {
+    match s {
+        "unmarked" => Ok(score_unmarked_sum),
+        "marked" => Ok(score_marked_sum),
+        _ => Err(format!("unknown scoring {}", s)),
     }
+}
+
+struct GameRules {
+    win: Vec<WinRule>,
+    score: ScoreFn,
+}
+
+impl Default for GameRules {
+    fn default() -> Self {
+        Self {
+            win: vec![WinRule::Rows, WinRule::Columns],
+            score: score_unmarked_sum,
+        }
+    }
+}
 
-    fn bingo(&self, numbers: &[i32]) -> bool {
-        for x in 0..5 {
-            let matches = numbers.iter().filter(|n| self.arr[x].contains(n)).count();
-            if matches == 5 {
-                return true;
+impl GameRules {
+    // Diagonals only exist on square boards, so some rules can't be won on every board
+    fn check(&self, boards: &[Board]) -> Result<(), String> {
+        for board in boards {
+            for rule in &self.win {
+                if rule.patterns(board.rows, board.cols).is_empty() {
+                    return Err(format!(
+                        "{:?} can't be won on the {}x{} board {}",
+                        rule, board.rows, board.cols, board.no
+                    ));
+                }
             }
-            let y: Vec<_> = self.arr.iter().map(|arr| arr[x]).collect();
-            let matches = numbers.iter().filter(|n| y.contains(n)).count();
-            if matches == 5 {
-                return true;
+        }
+        Ok(())
+    }
+}
+
+// Win patterns for one board shape, shared by all boards of that shape
+struct Layout {
+    cell_patterns: Vec<Vec<usize>>,
+    pattern_len: Vec<usize>,
+}
+
+impl Layout {
+    fn new(rows: usize, cols: usize, rules: &[WinRule]) -> Self {
+        let mut cell_patterns = vec![Vec::new(); rows * cols];
+        let mut pattern_len = Vec::new();
+        for pattern in rules.iter().flat_map(|r| r.patterns(rows, cols)) {
+            for c in &pattern {
+                cell_patterns[*c].push(pattern_len.len());
             }
+            pattern_len.push(pattern.len());
+        }
+        Self {
+            cell_patterns,
+            pattern_len,
         }
-        false
     }
 }
 
-fn first_bingo(inp: &[i32], boards: &[Board]) -> Option<(usize, usize)> {
-    for x in 5..inp.len() {
-        for b in boards.iter() {
-            if b.bingo(&inp[0..x]) {
-                return Some((x, b.no));
+struct WinEvent {
+    draw: usize,
+    number: i32,
+    board: usize,
+    score: i32,
+}
+
+struct BingoGame<'a> {
+    boards: &'a [Board],
+    score: ScoreFn,
+    layouts: Vec<Layout>,
+    board_layout: Vec<usize>,
+    // number -> (board, cell) for every cell holding that number
+    index: HashMap<i32, Vec<(usize, usize)>>,
+    cell_start: Vec<usize>,
+    marked: Vec<bool>,
+    pattern_start: Vec<usize>,
+    hits: Vec<usize>,
+    won: Vec<bool>,
+    draws: usize,
+}
+
+impl<'a> BingoGame<'a> {
+    fn new(boards: &'a [Board], rules: &GameRules) -> Self {
+        let mut shapes = HashMap::new();
+        let mut layouts = Vec::new();
+        let mut board_layout = Vec::with_capacity(boards.len());
+        let mut index: HashMap<i32, Vec<_>> = HashMap::new();
+        let mut cell_start = Vec::with_capacity(boards.len());
+        let mut pattern_start = Vec::with_capacity(boards.len());
+        let (mut cells, mut patterns) = (0, 0);
+        for (b, board) in boards.iter().enumerate() {
+            let l = *shapes.entry((board.rows, board.cols)).or_insert_with(|| {
+                layouts.push(Layout::new(board.rows, board.cols, &rules.win));
+                layouts.len() - 1
+            });
+            board_layout.push(l);
+            cell_start.push(cells);
+            pattern_start.push(patterns);
+            cells += board.cells.len();
+            patterns += layouts[l].pattern_len.len();
+            for (c, n) in board.cells.iter().enumerate() {
+                index.entry(*n).or_default().push((b, c));
             }
         }
+        Self {
+            boards,
+            score: rules.score,
+            layouts,
+            board_layout,
+            index,
+            cell_start,
+            marked: vec![false; cells],
+            pattern_start,
+            hits: vec![0; patterns],
+            won: vec![false; boards.len()],
+            draws: 0,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.marked.iter_mut().for_each(|m| *m = false);
+        self.hits.iter_mut().for_each(|h| *h = 0);
+        self.won.iter_mut().for_each(|w| *w = false);
+        self.draws = 0;
+    }
+
+    fn marked(&self, b: usize) -> &[bool] {
+        &self.marked[self.cell_start[b]..][..self.boards[b].cells.len()]
+    }
+
+    fn draw(&mut self, number: i32) -> Vec<WinEvent> {
+        let draw = self.draws;
+        self.draws += 1;
+        let mut events = Vec::new();
+        if let Some(cells) = self.index.get(&number) {
+            // a board can hold the number more than once, mark all cells before scoring
+            for group in cells.chunk_by(|x, y| x.0 == y.0) {
+                let b = group[0].0;
+                if self.won[b] {
+                    continue;
+                }
+                let layout = &self.layouts[self.board_layout[b]];
+                let mut bingo = false;
+                for &(_, c) in group {
+                    let cell = self.cell_start[b] + c;
+                    if self.marked[cell] {
+                        continue;
+                    }
+                    self.marked[cell] = true;
+                    for p in &layout.cell_patterns[c] {
+                        let hits = &mut self.hits[self.pattern_start[b] + p];
+                        *hits += 1;
+                        bingo |= *hits == layout.pattern_len[*p];
+                    }
+                }
+                if bingo {
+                    self.won[b] = true;
+                    let board = &self.boards[b];
+                    events.push(WinEvent {
+                        draw,
+                        number,
+                        board: board.no,
+                        score: (self.score)(board, self.marked(b), number),
+                    });
+                }
+            }
+        }
+        events
+    }
+
+    fn play(&mut self, numbers: &[i32]) -> Vec<WinEvent> {
+        numbers.iter().flat_map(|n| self.draw(*n)).collect()
+    }
+
+    fn never_won(&self) -> Vec<usize> {
+        self.boards
+            .iter()
+            .zip(&self.won)
+            .filter(|(_, w)| !**w)
+            .map(|(b, _)| b.no)
+            .collect()
+    }
+
+    fn standings(&mut self, numbers: &[i32]) -> Standings {
+        let finished = self.play(numbers);
+        Standings {
+            finished,
+            never_won: self.never_won(),
+        }
+    }
+
+    // Plays the game and writes the marked boards after every draw
+    fn replay<W: Write>(&mut self, numbers: &[i32], out: &mut W) -> io::Result<Standings> {
+        let mut finished = Vec::new();
+        for n in numbers {
+            let events = self.draw(*n);
+            writeln!(out, "Draw {}: {}", self.draws, n)?;
+            for e in &events {
+                writeln!(out, "Board {} wins with score {}", e.board, e.score)?;
+            }
+            writeln!(out, "{}", self)?;
+            finished.extend(events);
+        }
+        Ok(Standings {
+            finished,
+            never_won: self.never_won(),
+        })
     }
-    None
 }
 
-fn last_bingo(inp: &[i32], boards: &[Board], offset: usize) -> Option<(usize, usize)> {
-    let mut bb: Vec<_> = boards.iter().collect();
-    for x in offset..inp.len() {
-        let (filtered, tmp): (_, Vec<_>) = bb.into_iter().partition(|b| b.bingo(&inp[0..x]));
-        if tmp.is_empty() {
-            return Some((x, filtered[0].no));
+impl fmt::Display for BingoGame<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let width = self
+            .boards
+            .iter()
+            .flat_map(|b| b.cells.iter())
+            .map(|n| n.to_string().len())
+            .max()
+            .unwrap_or(1);
+        for (b, board) in self.boards.iter().enumerate() {
+            writeln!(
+                f,
+                "Board {}{}",
+                board.no,
+                if self.won[b] { " (won)" } else { "" }
+            )?;
+            let marked = self.marked(b);
+            for (row, row_marked) in board
+                .cells
+                .chunks(board.cols)
+                .zip(marked.chunks(board.cols))
+            {
+                for (n, m) in row.iter().zip(row_marked) {
+                    if *m {
+                        write!(f, "[{:>w$}]", n, w = width)?;
+                    } else {
+                        write!(f, " {:>w$} ", n, w = width)?;
+                    }
+                }
+                writeln!(f)?;
+            }
         }
-        bb = tmp
+        Ok(())
     }
-    None
+}
+
+struct Standings {
+    finished: Vec<WinEvent>,
+    never_won: Vec<usize>,
 }
 
 fn load_boards(inp: &[String]) -> Vec<Board> {
-    inp.chunks(6)
+    inp.split(|l| l.trim().is_empty())
+        .filter(|block| !block.is_empty())
         .enumerate()
         .map(|(n, lines)| {
             let x: Vec<Vec<i32>> = lines
                 .iter()
-                .skip(1)
                 .map(|s| s.split(' ').filter_map(|l| l.parse::<i32>().ok()).collect())
                 .collect();
             Board::from_vec(n, &x)
@@ -96,7 +394,77 @@ fn load_boards(inp: &[String]) -> Vec<Board> {
         .collect()
 }
 
+struct Args {
+    rules: GameRules,
+    replay: Option<String>,
+    trials: Option<usize>,
+    seed: u64,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut res = Args {
+        rules: GameRules::default(),
+        replay: None,
+        trials: None,
+        seed: 0,
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args.next().ok_or(format!("missing value for {}", arg))?;
+        match arg.as_str() {
+            "--rules" => {
+                res.rules.win = value
+                    .split(',')
+                    .map(WinRule::from_str)
+                    .collect::<Result<_, _>>()?
+            }
+            "--score" => res.rules.score = parse_score_fn(&value)?,
+            "--replay" => res.replay = Some(value),
+            "--trials" => match value.parse() {
+                Ok(0) => return Err("--trials must be positive".to_string()),
+                Ok(n) => res.trials = Some(n),
+                Err(e) => return Err(format!("--trials: {}", e)),
+            },
+            "--seed" => res.seed = value.parse().map_err(|e| format!("{}", e))?,
+            _ => return Err(format!("unknown argument {}", arg)),
+        }
+    }
+    Ok(res)
+}
+
+fn print_luck(boards: &[Board], args: &Args, draws: &[i32], trials: usize) {
+    let mut luck = montecarlo::simulate(boards, &args.rules, draws, trials, args.seed);
+    luck.sort_by(|a, b| b.wins.cmp(&a.wins).then(a.board.cmp(&b.board)));
+    println!("Win probabilities over {} shuffled draws:", trials);
+    for l in luck {
+        print!(
+            "Board {:>4} win {:>6.2}%",
+            l.board,
+            100.0 * l.win_probability(trials)
+        );
+        match l.expected_bingo_draw() {
+            Some(d) => print!(" expected bingo draw {:>6.2}", d),
+            None => print!(" never gets a bingo"),
+        }
+        if let Some((min, mean, median, max)) = l.score_summary() {
+            print!(
+                " score min {} mean {:.1} median {} max {}",
+                min, mean, median, max
+            );
+        }
+        println!();
+    }
+}
+
 fn main() {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            println!("{}", e);
+            println!("usage: day4 [--rules rows,columns,diagonals,corners,full] [--score unmarked|marked] [--replay file] [--trials n] [--seed n]");
+            return;
+        }
+    };
     let input = load_file("input.txt").unwrap();
     let inp: Vec<i32> = input
         .first()
@@ -105,16 +473,196 @@ fn main() {
         .filter_map(|s| s.parse().ok())
         .collect();
     let boards: Vec<Board> = load_boards(&input[1..]);
-    let winner = first_bingo(&inp, &boards);
-    if let Some((rounds, winner)) = winner {
-        println!("Winner is: {}", winner);
-        let score = boards[winner].calc_score(&inp[..rounds]);
-        println!("Score is {}", score);
-        let (last_round, last) = last_bingo(&inp, &boards, rounds).unwrap();
-        println!("Last Winer {}", last);
-        let score = boards[last].calc_score(&inp[..last_round]);
-        println!("Last Score {}", score);
+    if let Err(e) = args.rules.check(&boards) {
+        println!("{}", e);
+        return;
+    }
+    let mut game = BingoGame::new(&boards, &args.rules);
+    let standings = match &args.replay {
+        Some(path) => {
+            let mut out = io::BufWriter::new(File::create(path).unwrap());
+            game.replay(&inp, &mut out).unwrap()
+        }
+        None => game.standings(&inp),
+    };
+    let events = &standings.finished;
+    if let (Some(first), Some(last)) = (events.first(), events.last()) {
+        println!("Winner is: {}", first.board);
+        println!("Won at draw {} with {}", first.draw + 1, first.number);
+        println!("Score is {}", first.score);
+        println!("Last Winer {}", last.board);
+        println!("Won at draw {} with {}", last.draw + 1, last.number);
+        println!("Last Score {}", last.score);
     } else {
         println!("No Winner found!")
     }
+    println!("Standings:");
+    for (place, e) in events.iter().enumerate() {
+        println!(
+            "{:>4}. Board {:>4} at draw {:>4} score {}",
+            place + 1,
+            e.board,
+            e.draw + 1,
+            e.score
+        );
+    }
+    if !standings.never_won.is_empty() {
+        println!("Never won: {:?}", standings.never_won);
+    }
+    if let Some(trials) = args.trials {
+        print_luck(&boards, &args, &inp, trials);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const EXAMPLE: &str = "7,4,9,5,11,17,23,2,0,14,21,24,10,16,13,6,15,25,12,22,18,20,8,19,3,26,1
+
+22 13 17 11  0
+ 8  2 23  4 24
+21  9 14 16  7
+ 6 10  3 18  5
+ 1 12 20 15 19
+
+ 3 15  0  2 22
+ 9 18 13 17  5
+19  8  7 25 23
+20 11 10 24  4
+14 21 16 12  6
+
+14 21 17 24  4
+10 16 15  9 19
+18  8 23 26 20
+22 11 13  6  5
+ 2  0 12  3  7";
+
+    fn parse(inp: &str) -> (Vec<i32>, Vec<Board>) {
+        let lines: Vec<String> = inp.lines().map(String::from).collect();
+        let numbers = lines[0].split(',').map(|n| n.parse().unwrap()).collect();
+        (numbers, load_boards(&lines[1..]))
+    }
+
+    #[test]
+    fn test_example() {
+        let (numbers, boards) = parse(EXAMPLE);
+        let mut game = BingoGame::new(&boards, &GameRules::default());
+        let events = game.play(&numbers);
+        let first = events.first().unwrap();
+        assert_eq!((first.board, first.number, first.score), (2, 24, 4512));
+        let last = events.last().unwrap();
+        assert_eq!((last.board, last.number, last.score), (1, 13, 1924));
+    }
+
+    #[test]
+    fn test_standings() {
+        let unlucky =
+            "\n30 31 32 33 34\n35 36 37 38 39\n40 41 42 43 44\n45 46 47 48 49\n50 51 52 53 54";
+        let (numbers, boards) = parse(&(EXAMPLE.to_string() + "\n" + unlucky));
+        let mut game = BingoGame::new(&boards, &GameRules::default());
+        let standings = game.standings(&numbers);
+        let order: Vec<_> = standings
+            .finished
+            .iter()
+            .map(|e| (e.board, e.number, e.score))
+            .collect();
+        assert_eq!(order, vec![(2, 24, 4512), (0, 16, 2192), (1, 13, 1924)]);
+        assert_eq!(standings.never_won, vec![3]);
+    }
+
+    #[test]
+    fn test_replay() {
+        let boards = vec![Board::from_vec(0, &[vec![1, 2], vec![3, 4]])];
+        let mut game = BingoGame::new(&boards, &GameRules::default());
+        let mut out = Vec::new();
+        let standings = game.replay(&[1, 2], &mut out).unwrap();
+        assert_eq!(standings.finished.len(), 1);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Draw 1: 1\nBoard 0\n[1] 2 \n 3  4 \n\n\
+             Draw 2: 2\nBoard 0 wins with score 14\nBoard 0 (won)\n[1][2]\n 3  4 \n\n"
+        );
+    }
+
+    #[test]
+    fn test_simultaneous_wins() {
+        let boards = vec![
+            Board::from_vec(0, &[vec![1, 2], vec![3, 4]]),
+            Board::from_vec(1, &[vec![1, 5], vec![2, 6]]),
+            Board::from_vec(2, &[vec![2, 7], vec![8, 9]]),
+        ];
+        let mut game = BingoGame::new(&boards, &GameRules::default());
+        assert!(game.draw(1).is_empty());
+        // 2 completes a row of board 0, a column of board 1 and nothing on board 2
+        let events = game.draw(2);
+        let won: Vec<_> = events.iter().map(|e| (e.draw, e.board, e.score)).collect();
+        assert_eq!(won, vec![(1, 0, 14), (1, 1, 22)]);
+    }
+
+    #[test]
+    fn test_patterns() {
+        for rule in [
+            WinRule::Rows,
+            WinRule::Columns,
+            WinRule::Diagonals,
+            WinRule::FourCorners,
+            WinRule::FullCard,
+        ] {
+            assert_eq!(rule.patterns(1, 1), vec![vec![0]]);
+        }
+        assert_eq!(
+            WinRule::Diagonals.patterns(5, 5),
+            vec![vec![0, 6, 12, 18, 24], vec![4, 8, 12, 16, 20]]
+        );
+        assert_eq!(
+            WinRule::FourCorners.patterns(5, 5),
+            vec![vec![0, 4, 20, 24]]
+        );
+        assert_eq!(
+            WinRule::FullCard.patterns(5, 5),
+            vec![(0..25).collect::<Vec<_>>()]
+        );
+        assert_eq!(
+            WinRule::Rows.patterns(3, 4),
+            vec![vec![0, 1, 2, 3], vec![4, 5, 6, 7], vec![8, 9, 10, 11]]
+        );
+        assert_eq!(
+            WinRule::Columns.patterns(3, 4),
+            vec![vec![0, 4, 8], vec![1, 5, 9], vec![2, 6, 10], vec![3, 7, 11]]
+        );
+        assert!(WinRule::Diagonals.patterns(3, 4).is_empty());
+        assert_eq!(WinRule::FourCorners.patterns(3, 4), vec![vec![0, 3, 8, 11]]);
+        assert_eq!(
+            WinRule::FullCard.patterns(3, 4),
+            vec![(0..12).collect::<Vec<_>>()]
+        );
+    }
+
+    #[test]
+    fn test_check_rules() {
+        let boards = vec![
+            Board::from_vec(0, &[vec![1, 2], vec![3, 4]]),
+            Board::from_vec(1, &[vec![1, 2, 3], vec![4, 5, 6]]),
+        ];
+        let rules = GameRules {
+            win: vec![WinRule::Diagonals],
+            ..GameRules::default()
+        };
+        assert!(rules.check(&boards[..1]).is_ok());
+        assert!(rules.check(&boards).is_err());
+        assert!(GameRules::default().check(&boards).is_ok());
+    }
+
+    #[test]
+    fn test_repeated_number() {
+        // both 5 are marked before the board is scored
+        let boards = vec![Board::from_vec(0, &[vec![5, 1], vec![2, 5]])];
+        let mut game = BingoGame::new(&boards, &GameRules::default());
+        assert!(game.draw(1).is_empty());
+        let events = game.draw(5);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].score, 2 * 5);
+        assert_eq!(game.marked(0), &[true, true, false, true]);
+    }
 }
@@ -0,0 +1,124 @@
+use crate::{BingoGame, Board, GameRules};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use std::collections::HashMap;
+
+pub struct BoardLuck {
+    pub board: usize,
+    // trials in which the board was (one of) the first to win
+    pub wins: usize,
+    // trials in which the board got a bingo at all and the sum of those draws
+    pub bingos: usize,
+    pub draw_sum: usize,
+    // scores of the first place wins
+    pub scores: Vec<i32>,
+}
+
+impl BoardLuck {
+    pub fn win_probability(&self, trials: usize) -> f64 {
+        self.wins as f64 / trials as f64
+    }
+
+    // Mean draw of the board's bingo, whether or not it came first
+    pub fn expected_bingo_draw(&self) -> Option<f64> {
+        if self.bingos == 0 {
+            None
+        } else {
+            Some(self.draw_sum as f64 / self.bingos as f64)
+        }
+    }
+
+    // (min, mean, median, max) of the winning scores
+    pub fn score_summary(&self) -> Option<(i32, f64, i32, i32)> {
+        let mut scores = self.scores.clone();
+        scores.sort_unstable();
+        let (min, max) = (*scores.first()?, *scores.last()?);
+        let mean = scores.iter().map(|s| *s as f64).sum::<f64>() / scores.len() as f64;
+        Some((min, mean, scores[scores.len() / 2], max))
+    }
+}
+
+pub fn simulate(
+    boards: &[Board],
+    rules: &GameRules,
+    draws: &[i32],
+    trials: usize,
+    seed: u64,
+) -> Vec<BoardLuck> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut luck: Vec<_> = boards
+        .iter()
+        .map(|b| BoardLuck {
+            board: b.no,
+            wins: 0,
+            bingos: 0,
+            draw_sum: 0,
+            scores: Vec::new(),
+        })
+        .collect();
+    let pos: HashMap<_, _> = boards.iter().enumerate().map(|(i, b)| (b.no, i)).collect();
+    let mut game = BingoGame::new(boards, rules);
+    let mut order = draws.to_vec();
+    for _ in 0..trials {
+        order.shuffle(&mut rng);
+        game.reset();
+        let events = game.play(&order);
+        let first_draw = events.first().map(|e| e.draw);
+        for e in events {
+            let l = &mut luck[pos[&e.board]];
+            l.bingos += 1;
+            l.draw_sum += e.draw + 1;
+            if Some(e.draw) == first_draw {
+                l.wins += 1;
+                l.scores.push(e.score);
+            }
+        }
+    }
+    luck
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn summary(luck: &[BoardLuck]) -> Vec<(usize, usize, usize, usize, Vec<i32>)> {
+        luck.iter()
+            .map(|l| (l.board, l.wins, l.bingos, l.draw_sum, l.scores.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_identical_boards() {
+        let cells = vec![vec![1, 2, 3], vec![4, 5, 6], vec![7, 8, 9]];
+        let boards: Vec<_> = (0..3).map(|no| Board::from_vec(no, &cells)).collect();
+        let draws: Vec<_> = (1..=9).collect();
+        let luck = simulate(&boards, &GameRules::default(), &draws, 50, 7);
+        for l in &luck {
+            assert_eq!(l.wins, 50);
+            assert_eq!(l.bingos, 50);
+            assert_eq!(l.win_probability(50), 1.0);
+        }
+        // all boards win at the same draws with the same scores
+        assert_eq!(luck[0].scores, luck[2].scores);
+        assert_eq!(luck[0].draw_sum, luck[1].draw_sum);
+    }
+
+    #[test]
+    fn test_seed() {
+        let boards = vec![
+            Board::from_vec(0, &[vec![1, 2], vec![3, 4]]),
+            Board::from_vec(1, &[vec![4, 5], vec![6, 7]]),
+        ];
+        let draws: Vec<_> = (1..=7).collect();
+        let rules = GameRules::default();
+        let luck = simulate(&boards, &rules, &draws, 100, 42);
+        assert_eq!(
+            summary(&luck),
+            summary(&simulate(&boards, &rules, &draws, 100, 42))
+        );
+        // a board with all its numbers drawn always gets a bingo
+        assert!(luck.iter().all(|l| l.bingos == 100));
+        assert!(luck.iter().map(|l| l.wins).sum::<usize>() >= 100);
+    }
+}
@@ -0,0 +1,219 @@
+use crate::Line;
+use std::collections::{HashMap, HashSet};
+
+type Point = (i64, i64);
+// Lattice line through the origin direction (dx, dy) and c = dy * x - dx * y
+type LineKey = (i64, i64, i128);
+
+// Products of u32 sized coordinates and steps don't fit into i64
+fn cross(a: Point, b: Point) -> i128 {
+    a.0 as i128 * b.1 as i128 - a.1 as i128 * b.0 as i128
+}
+
+// Lattice points of a line as start + k * step for k in 0..=steps
+struct Segment {
+    start: Point,
+    step: Point,
+    steps: i64,
+}
+
+impl Segment {
+    fn new(line: &Line) -> Self {
+        let (dx, dy, steps) = line.lattice_step();
+        Self {
+            start: (line.x1 as i64, line.y1 as i64),
+            // a single point gets an arbitrary direction
+            step: if steps == 0 { (1, 0) } else { (dx, dy) },
+            steps,
+        }
+    }
+
+    fn at(&self, k: i64) -> Point {
+        (
+            self.start.0 + k * self.step.0,
+            self.start.1 + k * self.step.1,
+        )
+    }
+}
+
+fn canonical(d: Point) -> Point {
+    if d.0 < 0 || (d.0 == 0 && d.1 < 0) {
+        (-d.0, -d.1)
+    } else {
+        d
+    }
+}
+
+fn line_key(d: Point, p: Point) -> LineKey {
+    (d.0, d.1, cross(p, d))
+}
+
+// Position of a point along a line with canonical direction d
+fn coord(d: Point, p: Point) -> i64 {
+    if d.0 > 0 {
+        p.0
+    } else {
+        p.1
+    }
+}
+
+fn coord_step(d: Point) -> i64 {
+    if d.0 > 0 {
+        d.0
+    } else {
+        d.1
+    }
+}
+
+fn point_at(key: LineKey, u: i64) -> Point {
+    let (dx, dy, c) = key;
+    if dx > 0 {
+        (u, ((dy as i128 * u as i128 - c) / dx as i128) as i64)
+    } else {
+        ((c / dy as i128) as i64, u)
+    }
+}
+
+enum Intersection {
+    Crossing(Point),
+    Overlap(LineKey, i64, i64),
+}
+
+fn intersect(a: &Segment, b: &Segment) -> Option<Intersection> {
+    let w = (b.start.0 - a.start.0, b.start.1 - a.start.1);
+    let cr = cross(a.step, b.step);
+    if cr != 0 {
+        let (ni, nj) = (cross(w, b.step), cross(w, a.step));
+        if ni % cr != 0 || nj % cr != 0 {
+            return None;
+        }
+        let (ki, kj) = (ni / cr, nj / cr);
+        if (0..=a.steps as i128).contains(&ki) && (0..=b.steps as i128).contains(&kj) {
+            Some(Intersection::Crossing(a.at(ki as i64)))
+        } else {
+            None
+        }
+    } else if cross(w, a.step) == 0 {
+        let d = canonical(a.step);
+        let ua = (coord(d, a.start), coord(d, a.at(a.steps)));
+        let ub = (coord(d, b.start), coord(d, b.at(b.steps)));
+        let lo = ua.0.min(ua.1).max(ub.0.min(ub.1));
+        let hi = ua.0.max(ua.1).min(ub.0.max(ub.1));
+        if lo <= hi {
+            Some(Intersection::Overlap(line_key(d, a.start), lo, hi))
+        } else {
+            None
+        }
+    } else {
+        None
+    }
+}
+
+// Counts points covered by more than one vent line without a grid
+pub struct VentIntersections {
+    // merged collinear overlaps per lattice line as coordinate ranges
+    overlaps: HashMap<LineKey, Vec<(i64, i64)>>,
+    // single crossing points not inside any overlap
+    crossings: HashSet<Point>,
+    // points where overlaps on different lines meet, with the number of lines
+    shared: HashMap<Point, usize>,
+}
+
+impl VentIntersections {
+    pub fn new(lines: &[&Line]) -> Self {
+        let segments: Vec<_> = lines.iter().map(|l| Segment::new(l)).collect();
+        let mut overlaps: HashMap<LineKey, Vec<(i64, i64)>> = HashMap::new();
+        let mut crossings = HashSet::new();
+        for (i, a) in segments.iter().enumerate() {
+            for b in &segments[i + 1..] {
+                match intersect(a, b) {
+                    Some(Intersection::Crossing(p)) => {
+                        crossings.insert(p);
+                    }
+                    Some(Intersection::Overlap(k, lo, hi)) => {
+                        overlaps.entry(k).or_default().push((lo, hi))
+                    }
+                    None => {}
+                }
+            }
+        }
+        for ranges in overlaps.values_mut() {
+            ranges.sort_unstable();
+            let mut merged: Vec<(i64, i64)> = Vec::with_capacity(ranges.len());
+            for r in ranges.iter() {
+                match merged.last_mut() {
+                    Some(last) if r.0 <= last.1 => last.1 = last.1.max(r.1),
+                    _ => merged.push(*r),
+                }
+            }
+            *ranges = merged;
+        }
+        let mut res = Self {
+            overlaps,
+            crossings: HashSet::new(),
+            shared: HashMap::new(),
+        };
+        let directions: HashSet<_> = res.overlaps.keys().map(|k| (k.0, k.1)).collect();
+        let covering =
+            |res: &Self, p: &Point| directions.iter().filter(|d| res.covers(**d, *p)).count();
+        let merged: Vec<_> = res
+            .overlaps
+            .iter()
+            .flat_map(|(k, ranges)| {
+                let step = coord_step((k.0, k.1));
+                ranges.iter().map(move |r| Segment {
+                    start: point_at(*k, r.0),
+                    step: (k.0, k.1),
+                    steps: (r.1 - r.0) / step,
+                })
+            })
+            .collect();
+        let mut shared = HashSet::new();
+        for (i, a) in merged.iter().enumerate() {
+            for b in &merged[i + 1..] {
+                if let Some(Intersection::Crossing(p)) = intersect(a, b) {
+                    shared.insert(p);
+                }
+            }
+        }
+        res.shared = shared.iter().map(|p| (*p, covering(&res, p))).collect();
+        res.crossings = crossings
+            .into_iter()
+            .filter(|p| covering(&res, p) == 0)
+            .collect();
+        res
+    }
+    fn covers(&self, d: Point, p: Point) -> bool {
+        let u = coord(d, p);
+        self.overlaps.get(&line_key(d, p)).is_some_and(|ranges| {
+            let i = ranges.partition_point(|r| r.1 < u);
+            i < ranges.len() && ranges[i].0 <= u
+        })
+    }
+
+    pub fn calc_danger_level(&self) -> usize {
+        let overlapping: i64 = self
+            .overlaps
+            .iter()
+            .flat_map(|(k, ranges)| {
+                let step = coord_step((k.0, k.1));
+                ranges.iter().map(move |r| (r.1 - r.0) / step + 1)
+            })
+            .sum();
+        let counted_twice: usize = self.shared.values().map(|n| n - 1).sum();
+        overlapping as usize + self.crossings.len() - counted_twice
+    }
+
+    pub fn points(&self) -> Vec<Point> {
+        let mut res: Vec<_> = self.crossings.iter().copied().collect();
+        for (k, ranges) in &self.overlaps {
+            let step = coord_step((k.0, k.1)) as usize;
+            for r in ranges {
+                res.extend((r.0..=r.1).step_by(step).map(|u| point_at(*k, u)));
+            }
+        }
+        res.sort_unstable();
+        res.dedup();
+        res
+    }
+}
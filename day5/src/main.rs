@@ -1,7 +1,11 @@
+mod intersect;
+mod render;
+use intersect::VentIntersections;
 use regex::Regex;
+use std::collections::HashMap;
 use std::fmt;
 use std::fs::File;
-use std::io::{self, BufRead};
+use std::io::{self, BufRead, Write};
 use std::path::Path;
 
 fn load_input<P>(filename: P) -> io::Result<Vec<Line>>
@@ -13,7 +17,7 @@ where
     let buf = io::BufReader::new(file);
     Ok(buf
         .lines()
-        .filter_map(|line| line.ok())
+        .map_while(Result::ok)
         .filter_map(|line| {
             re.captures(&line).map(|cap| Line {
                 x1: cap[1].parse().unwrap(),
@@ -25,6 +29,14 @@ where
         .collect())
 }
 
+fn gcd(a: i64, b: i64) -> i64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
 struct Line {
     x1: u32,
     y1: u32,
@@ -56,6 +68,22 @@ impl Line {
         self.y1 == self.y2
     }
 
+    fn diagonal(&self) -> bool {
+        self.end_x() - self.start_x() == self.end_y() - self.start_y()
+    }
+
+    // Smallest step between lattice points on the line and the number of steps
+    fn lattice_step(&self) -> (i64, i64, i64) {
+        let dx = self.x2 as i64 - self.x1 as i64;
+        let dy = self.y2 as i64 - self.y1 as i64;
+        let g = gcd(dx.abs(), dy.abs());
+        if g == 0 {
+            (0, 0, 0)
+        } else {
+            (dx / g, dy / g, g)
+        }
+    }
+
     fn diag_direction(&self) -> (i32, i32) {
         if self.x1 > self.x2 {
             if self.y1 > self.y2 {
@@ -81,66 +109,79 @@ impl fmt::Display for Line {
 enum HydroOperationMode {
     None,
     Diag,
+    AnyAngle,
+}
+
+impl HydroOperationMode {
+    fn accepts(&self, line: &Line) -> bool {
+        match self {
+            HydroOperationMode::None => line.horizontal() || line.vertical(),
+            HydroOperationMode::Diag => line.horizontal() || line.vertical() || line.diagonal(),
+            HydroOperationMode::AnyAngle => true,
+        }
+    }
 }
 
+// Sparse map of all points covered by at least one vent
 struct HydroVenture {
-    map: Vec<Vec<u32>>,
+    map: HashMap<(u32, u32), u32>,
+    // indices of lines the operation mode can't mark
+    skipped: Vec<usize>,
 }
 
 impl HydroVenture {
     fn new() -> Self {
-        Self { map: Vec::new() }
+        Self {
+            map: HashMap::new(),
+            skipped: Vec::new(),
+        }
     }
 
-    fn find_max(lines: &[Line]) -> (usize, usize) {
-        let x = lines.iter().map(Line::end_x).max().unwrap();
-        let y = lines.iter().map(Line::end_y).max().unwrap();
-        (x as usize, y as usize)
+    fn mark_point(&mut self, x: u32, y: u32) {
+        *self.map.entry((x, y)).or_insert(0) += 1;
     }
 
-    fn mark_vent(&mut self, line: &Line, mode: &HydroOperationMode) {
-        // Only horizontal or vertical line are possible
+    fn mark_vent(&mut self, line: &Line, mode: &HydroOperationMode) -> bool {
+        // Diagonal lines need the Diag mode and any other angle the AnyAngle mode
         if line.horizontal() {
             for y in line.start_y()..=line.end_y() {
-                self.map[y as usize][line.x1 as usize] += 1;
+                self.mark_point(line.x1, y);
             }
         } else if line.vertical() {
             for x in line.start_x()..=line.end_x() {
-                self.map[line.y1 as usize][x as usize] += 1;
+                self.mark_point(x, line.y1);
             }
-        } else if *mode == HydroOperationMode::Diag {
+        } else if *mode == HydroOperationMode::Diag && line.diagonal() {
             let (dx, dy) = line.diag_direction();
-            let rx: Vec<_> = if dx == 1 {
-                (line.x1..=line.x2).rev().collect()
-            } else {
-                (line.x2..=line.x1).into_iter().collect()
-            };
-            let ry: Vec<_> = if dy == 1 {
-                (line.y1..=line.y2).rev().collect()
-            } else {
-                (line.y2..=line.y1).into_iter().collect()
-            };
-            for (x, y) in rx.into_iter().zip(ry.into_iter()) {
-                self.map[y as usize][x as usize] += 1;
+            for k in 0..=(line.end_x() - line.start_x()) as i64 {
+                let x = line.x1 as i64 + k * dx as i64;
+                let y = line.y1 as i64 + k * dy as i64;
+                self.mark_point(x as u32, y as u32);
+            }
+        } else if *mode == HydroOperationMode::AnyAngle {
+            let (dx, dy, steps) = line.lattice_step();
+            for k in 0..=steps {
+                let x = line.x1 as i64 + k * dx;
+                let y = line.y1 as i64 + k * dy;
+                self.mark_point(x as u32, y as u32);
             }
+        } else {
+            return false;
         }
+        true
     }
 
-    fn mark_vents(self, lines: &[Line], mode: HydroOperationMode) -> Self {
-        let (max_x, max_y) = Self::find_max(lines);
-        let map = vec![vec![0; max_x + 1]; max_y + 1];
-        let mut s = Self { map };
-        for l in lines {
-            s.mark_vent(l, &mode);
+    fn mark_vents(mut self, lines: &[Line], mode: HydroOperationMode) -> Self {
+        for (i, l) in lines.iter().enumerate() {
+            if !self.mark_vent(l, &mode) {
+                self.skipped.push(i);
+            }
         }
-        s
+        self
     }
 
     fn calc_danger_level(&self) -> usize {
-        self.map
-            .iter()
-            .map(|r| r.iter().filter(|n| **n > 1).count())
-            .sum()
+        self.map.values().filter(|n| **n > 1).count()
     }
 }
 
@@ -152,12 +193,13 @@ impl Default for HydroVenture {
 
 impl fmt::Display for HydroVenture {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        for row in self.map.iter() {
-            for col in row {
-                if *col == 0 {
-                    write!(f, ".")?;
-                } else {
-                    write!(f, "{}", col)?;
+        let max_x = self.map.keys().map(|(x, _)| *x).max().unwrap_or(0);
+        let max_y = self.map.keys().map(|(_, y)| *y).max().unwrap_or(0);
+        for y in 0..=max_y {
+            for x in 0..=max_x {
+                match self.map.get(&(x, y)) {
+                    Some(n) => write!(f, "{}", n)?,
+                    None => write!(f, ".")?,
                 }
             }
             writeln!(f)?;
@@ -166,14 +208,198 @@ impl fmt::Display for HydroVenture {
     }
 }
 
+fn print_skipped(hydro: &HydroVenture, lines: &[Line]) {
+    if !hydro.skipped.is_empty() {
+        println!("Skipped {} lines", hydro.skipped.len());
+        for i in hydro.skipped.iter().take(5) {
+            println!("  line {}: {}", i + 1, lines[*i]);
+        }
+    }
+}
+
+fn calc_analytic(lines: &[Line], mode: HydroOperationMode) -> VentIntersections {
+    let accepted: Vec<_> = lines.iter().filter(|l| mode.accepts(l)).collect();
+    VentIntersections::new(&accepted)
+}
+
 fn main() {
     let inp = load_input("input.txt").unwrap();
 
     let hydro = HydroVenture::new();
     let hydro = hydro.mark_vents(&inp, HydroOperationMode::None);
     println!("Part1: Danger level: {}", hydro.calc_danger_level());
+    print_skipped(&hydro, &inp);
+    let analytic = calc_analytic(&inp, HydroOperationMode::None);
+    println!(
+        "Part1: Analytic danger level: {}",
+        analytic.calc_danger_level()
+    );
 
     let hydro = HydroVenture::new();
     let hydro = hydro.mark_vents(&inp, HydroOperationMode::Diag);
     println!("Part2: Danger level: {}", hydro.calc_danger_level());
+    print_skipped(&hydro, &inp);
+    let analytic = calc_analytic(&inp, HydroOperationMode::Diag);
+    println!(
+        "Part2: Analytic danger level: {}",
+        analytic.calc_danger_level()
+    );
+
+    let hydro = HydroVenture::new();
+    let hydro = hydro.mark_vents(&inp, HydroOperationMode::AnyAngle);
+    println!("Any angle: Danger level: {}", hydro.calc_danger_level());
+    let analytic = calc_analytic(&inp, HydroOperationMode::AnyAngle);
+    println!(
+        "Any angle: Analytic danger level: {}",
+        analytic.calc_danger_level()
+    );
+
+    let args: Vec<_> = std::env::args().skip(1).collect();
+    // check all flags before any file gets created
+    for arg in args.chunks(2) {
+        if !matches!(
+            (arg[0].as_str(), arg.get(1)),
+            ("--points" | "--pgm" | "--svg", Some(_))
+        ) {
+            println!("unknown argument {}", arg.join(" "));
+            println!("usage: day5 [--points file] [--pgm file] [--svg file]");
+            return;
+        }
+    }
+    for arg in args.chunks(2) {
+        let (flag, path) = (arg[0].as_str(), &arg[1]);
+        // exports always use the any angle map, which includes the Part2 lines
+        println!("Writing any angle {} to {}", &flag[2..], path);
+        let mut out = io::BufWriter::new(File::create(path).unwrap());
+        let res = match flag {
+            "--points" => analytic
+                .points()
+                .iter()
+                .try_for_each(|(x, y)| writeln!(out, "{},{}", x, y)),
+            "--pgm" => render::write_pgm(&hydro, &mut out),
+            _ => render::write_svg(&hydro, &inp, &mut out),
+        };
+        if let Err(e) = res {
+            println!("{}: {}", path, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn lines(inp: &[(u32, u32, u32, u32)]) -> Vec<Line> {
+        inp.iter()
+            .map(|(x1, y1, x2, y2)| Line {
+                x1: *x1,
+                y1: *y1,
+                x2: *x2,
+                y2: *y2,
+            })
+            .collect()
+    }
+
+    fn check(lines: &[Line]) {
+        let hydro = HydroVenture::new().mark_vents(lines, HydroOperationMode::AnyAngle);
+        let analytic = calc_analytic(lines, HydroOperationMode::AnyAngle);
+        assert_eq!(analytic.calc_danger_level(), hydro.calc_danger_level());
+        let mut expected: Vec<_> = hydro
+            .map
+            .iter()
+            .filter(|(_, n)| **n > 1)
+            .map(|((x, y), _)| (*x as i64, *y as i64))
+            .collect();
+        expected.sort_unstable();
+        assert_eq!(analytic.points(), expected);
+    }
+
+    #[test]
+    fn test_simple() {
+        let inp = lines(&[
+            (0, 9, 5, 9),
+            (8, 0, 0, 8),
+            (9, 4, 3, 4),
+            (2, 2, 2, 1),
+            (7, 0, 7, 4),
+            (6, 4, 2, 0),
+            (0, 9, 2, 9),
+            (3, 4, 1, 4),
+            (0, 0, 8, 8),
+            (5, 5, 8, 2),
+        ]);
+        assert_eq!(
+            calc_analytic(&inp, HydroOperationMode::None).calc_danger_level(),
+            5
+        );
+        assert_eq!(
+            calc_analytic(&inp, HydroOperationMode::Diag).calc_danger_level(),
+            12
+        );
+        check(&inp);
+    }
+
+    #[test]
+    fn test_skipped() {
+        let inp = lines(&[(0, 0, 4, 0), (0, 0, 4, 4), (0, 0, 4, 2), (1, 3, 1, 0)]);
+        let hydro = HydroVenture::new().mark_vents(&inp, HydroOperationMode::None);
+        assert_eq!(hydro.skipped, vec![1, 2]);
+        let hydro = HydroVenture::new().mark_vents(&inp, HydroOperationMode::Diag);
+        assert_eq!(hydro.skipped, vec![2]);
+        assert_eq!(hydro.calc_danger_level(), 3);
+        let hydro = HydroVenture::new().mark_vents(&inp, HydroOperationMode::AnyAngle);
+        assert!(hydro.skipped.is_empty());
+        // the any angle line only meets the others at the origin
+        assert_eq!(hydro.calc_danger_level(), 3);
+    }
+
+    #[test]
+    fn test_huge_extents() {
+        // lattice steps and coordinates near u32::MAX overflow i64 products
+        let m = u32::MAX;
+        check(&lines(&[
+            (m, m, 0, 1),
+            (0, 1, m, m),
+            (m, 1, 0, m),
+            (m, 1, m, 4),
+            (m, m, m - 3, m),
+            (m - 2, m - 1, m - 2, m - 4),
+            (m - 5, m - 3, m, m - 3),
+        ]));
+    }
+
+    #[test]
+    fn test_render_extent() {
+        let m = u32::MAX;
+        // vents at both ends of the u32 range would need a huge raster
+        let inp = lines(&[(0, 5, 0, 0), (m, 1, m, 0), (2, 3, 2, 1)]);
+        let hydro = HydroVenture::new().mark_vents(&inp, HydroOperationMode::None);
+        let mut out = Vec::new();
+        assert!(render::write_pgm(&hydro, &mut out).is_err());
+        assert!(out.is_empty());
+        render::write_svg(&hydro, &inp, &mut out).unwrap();
+        let svg = String::from_utf8(out).unwrap();
+        assert!(svg.contains(&format!("{} 6", m as u64 + 1)));
+    }
+
+    #[test]
+    fn test_collinear_and_any_angle() {
+        check(&lines(&[
+            (0, 0, 6, 3),
+            (2, 1, 10, 5),
+            (4, 2, 4, 2),
+            (0, 3, 12, 3),
+            (3, 0, 3, 9),
+            (9, 0, 0, 9),
+            (6, 3, 12, 0),
+            (1, 1, 7, 7),
+            (7, 7, 1, 1),
+            (0, 5, 8, 5),
+            (2, 5, 10, 5),
+            (5, 0, 5, 8),
+            (5, 2, 5, 10),
+            (0, 10, 10, 0),
+            (1, 9, 9, 1),
+        ]));
+    }
 }
@@ -0,0 +1,72 @@
+use crate::{HydroVenture, Line};
+use std::io::{self, Write};
+
+fn bounds(points: impl Iterator<Item = (u32, u32)>) -> Option<(u32, u32, u32, u32)> {
+    points.fold(None, |b, (x, y)| match b {
+        None => Some((x, y, x, y)),
+        Some((x0, y0, x1, y1)) => Some((x0.min(x), y0.min(y), x1.max(x), y1.max(y))),
+    })
+}
+
+// Largest raster written, vents can span the whole u32 range
+const MAX_PIXELS: u64 = 1 << 28;
+
+// Grayscale heatmap, 1 vent is dim and overlapping vents get brighter with the count
+pub fn write_pgm<W: Write>(hydro: &HydroVenture, out: &mut W) -> io::Result<()> {
+    let (min_x, min_y, max_x, max_y) = bounds(hydro.map.keys().copied()).unwrap_or_default();
+    let (w, h) = ((max_x - min_x) as u64 + 1, (max_y - min_y) as u64 + 1);
+    if w * h > MAX_PIXELS {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("extent {}x{} is too large for a raster, use --svg", w, h),
+        ));
+    }
+    let (w, h) = (w as usize, h as usize);
+    let max_count = hydro.map.values().copied().max().unwrap_or(1).max(2);
+    let mut img = vec![0u8; w * h];
+    for ((x, y), n) in &hydro.map {
+        img[(y - min_y) as usize * w + (x - min_x) as usize] = if *n > 1 {
+            (160 + 95 * (n - 2) / (max_count - 2).max(1)) as u8
+        } else {
+            64
+        };
+    }
+    write!(out, "P5\n{} {}\n255\n", w, h)?;
+    out.write_all(&img)
+}
+
+// Vent lines in gray with the dangerous points on top in red
+pub fn write_svg<W: Write>(hydro: &HydroVenture, lines: &[Line], out: &mut W) -> io::Result<()> {
+    let ends = lines.iter().flat_map(|l| vec![(l.x1, l.y1), (l.x2, l.y2)]);
+    let (min_x, min_y, max_x, max_y) = bounds(ends).unwrap_or_default();
+    writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="{} {} {} {}">"#,
+        min_x as f64 - 0.5,
+        min_y as f64 - 0.5,
+        (max_x - min_x) as u64 + 1,
+        (max_y - min_y) as u64 + 1
+    )?;
+    writeln!(out, r#"<g stroke="gray" stroke-width="0.5">"#)?;
+    for l in lines {
+        writeln!(
+            out,
+            r#"<line x1="{}" y1="{}" x2="{}" y2="{}"/>"#,
+            l.x1, l.y1, l.x2, l.y2
+        )?;
+    }
+    writeln!(out, "</g>")?;
+    writeln!(out, r#"<g fill="red">"#)?;
+    for ((x, y), n) in &hydro.map {
+        if *n > 1 {
+            writeln!(
+                out,
+                r#"<rect x="{}" y="{}" width="1" height="1"/>"#,
+                *x as f64 - 0.5,
+                *y as f64 - 0.5
+            )?;
+        }
+    }
+    writeln!(out, "</g>")?;
+    writeln!(out, "</svg>")
+}
@@ -1,5 +1,7 @@
+use num_bigint::BigUint;
+use std::fmt;
 use std::fs::File;
-use std::io::{self, BufRead};
+use std::io::{self, BufRead, Write};
 use std::path::Path;
 
 fn load_input<P>(filename: P) -> io::Result<Vec<u64>>
@@ -14,26 +16,442 @@ where
     Ok(res)
 }
 
-fn calc_fish_count(fishes: &[u64], n: u64) -> usize {
-    // group all fishes into days left to birth
-    let mut grouped: Vec<_> = (0..=8)
-        .map(|x| fishes.iter().filter(|f| x == **f).count())
-        .collect();
-    for _ in 0..n {
-        // for the count of day0 fishes are created (through rotate left)
-        let cnt_0days = grouped[0];
-        grouped.rotate_left(1);
-        // ay 0 fished get set to 6 days left
-        grouped[6] += cnt_0days;
+// Number type for the fish counts, None signals an overflow
+trait Count: Clone {
+    // n in the same number system as self (needed for the modulus)
+    fn lift(&self, n: u64) -> Self;
+    fn add(&self, other: &Self) -> Option<Self>;
+    fn mul(&self, other: &Self) -> Option<Self>;
+}
+
+impl Count for u128 {
+    fn lift(&self, n: u64) -> Self {
+        n as u128
+    }
+
+    fn add(&self, other: &Self) -> Option<Self> {
+        self.checked_add(*other)
+    }
+
+    fn mul(&self, other: &Self) -> Option<Self> {
+        self.checked_mul(*other)
+    }
+}
+
+impl Count for BigUint {
+    fn lift(&self, n: u64) -> Self {
+        BigUint::from(n)
+    }
+
+    fn add(&self, other: &Self) -> Option<Self> {
+        Some(self + other)
+    }
+
+    fn mul(&self, other: &Self) -> Option<Self> {
+        Some(self * other)
+    }
+}
+
+#[derive(Clone)]
+struct ModP {
+    v: u64,
+    p: u64,
+}
+
+impl ModP {
+    fn new(v: u64, p: u64) -> Self {
+        assert!(p > 0, "modulus must be positive");
+        Self { v: v % p, p }
+    }
+}
+
+impl fmt::Display for ModP {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (mod {})", self.v, self.p)
+    }
+}
+
+impl Count for ModP {
+    fn lift(&self, n: u64) -> Self {
+        ModP::new(n, self.p)
+    }
+
+    fn add(&self, other: &Self) -> Option<Self> {
+        Some(ModP::new(
+            ((self.v as u128 + other.v as u128) % self.p as u128) as u64,
+            self.p,
+        ))
     }
-    // count all fishes in all day groups
-    grouped.iter().sum()
+
+    fn mul(&self, other: &Self) -> Option<Self> {
+        Some(ModP::new(
+            ((self.v as u128 * other.v as u128) % self.p as u128) as u64,
+            self.p,
+        ))
+    }
+}
+
+#[derive(Clone)]
+struct Matrix<T> {
+    n: usize,
+    cells: Vec<T>,
+}
+
+impl<T: Count> Matrix<T> {
+    fn identity(n: usize, one: &T) -> Self {
+        let cells = (0..n * n)
+            .map(|i| one.lift((i / n == i % n) as u64))
+            .collect();
+        Self { n, cells }
+    }
+
+    fn mul(&self, other: &Self) -> Option<Self> {
+        let n = self.n;
+        let mut cells = Vec::with_capacity(n * n);
+        for r in 0..n {
+            for c in 0..n {
+                let mut sum = self.cells[0].lift(0);
+                for k in 0..n {
+                    sum = sum.add(&self.cells[r * n + k].mul(&other.cells[k * n + c])?)?;
+                }
+                cells.push(sum);
+            }
+        }
+        Some(Self { n, cells })
+    }
+
+    fn apply(&self, v: &[T]) -> Option<Vec<T>> {
+        let mut res = Vec::with_capacity(self.n);
+        for r in 0..self.n {
+            let mut sum = v[0].lift(0);
+            for (c, x) in v.iter().enumerate() {
+                sum = sum.add(&self.cells[r * self.n + c].mul(x)?)?;
+            }
+            res.push(sum);
+        }
+        Some(res)
+    }
+
+    fn pow(&self, mut e: u64) -> Option<Self> {
+        let mut res = Self::identity(self.n, &self.cells[0].lift(1));
+        let mut base = self.clone();
+        while e > 0 {
+            if e & 1 == 1 {
+                res = res.mul(&base)?;
+            }
+            e >>= 1;
+            if e > 0 {
+                base = base.mul(&base)?;
+            }
+        }
+        Some(res)
+    }
+}
+
+struct LifecycleModel {
+    // days between two births of a grown up fish
+    cycle: usize,
+    // extra days before a newborn gives birth the first time
+    newborn_delay: usize,
+    // age in days at which a fish dies, initial fishes start with age 0
+    mortality: Option<usize>,
+    // fishes born per birth
+    offspring: u64,
+}
+
+impl Default for LifecycleModel {
+    fn default() -> Self {
+        Self {
+            cycle: 7,
+            newborn_delay: 2,
+            mortality: None,
+            offspring: 1,
+        }
+    }
+}
+
+impl LifecycleModel {
+    fn reset_timer(&self) -> usize {
+        self.cycle - 1
+    }
+
+    fn newborn_timer(&self) -> usize {
+        self.cycle - 1 + self.newborn_delay
+    }
+
+    fn timers(&self, fishes: &[u64]) -> usize {
+        let max_fish = fishes.iter().max().copied().unwrap_or(0) as usize;
+        self.newborn_timer().max(max_fish) + 1
+    }
+
+    fn ages(&self) -> usize {
+        self.mortality.unwrap_or(1)
+    }
+
+    // States are (timer, age) pairs, without mortality the age is always 0
+    fn state(&self, timer: usize, age: usize) -> usize {
+        timer * self.ages() + age
+    }
+
+    // next[state] = sum over prev states of transition[state][prev] * prev count
+    fn transition<T: Count>(&self, timers: usize, one: &T) -> Matrix<T> {
+        let n = timers * self.ages();
+        let mut m = Matrix {
+            n,
+            cells: vec![one.lift(0); n * n],
+        };
+        for timer in 0..timers {
+            for age in 0..self.ages() {
+                let next_age = match self.mortality {
+                    Some(mortality) if age + 1 >= mortality => continue,
+                    Some(_) => age + 1,
+                    None => 0,
+                };
+                let prev = self.state(timer, age);
+                if timer == 0 {
+                    // day 0 fishes reset and give birth to newborns
+                    let reset = self.state(self.reset_timer(), next_age);
+                    m.cells[reset * n + prev] = one.lift(1);
+                    let born = self.state(self.newborn_timer(), 0);
+                    m.cells[born * n + prev] = one.lift(self.offspring);
+                } else {
+                    m.cells[self.state(timer - 1, next_age) * n + prev] = one.lift(1);
+                }
+            }
+        }
+        m
+    }
+
+    fn initial<T: Count>(&self, fishes: &[u64], one: &T) -> Vec<T> {
+        let timers = self.timers(fishes);
+        let mut res = vec![one.lift(0); timers * self.ages()];
+        // group all fishes into days left to birth
+        for timer in 0..timers {
+            let count = fishes.iter().filter(|f| **f as usize == timer).count();
+            res[self.state(timer, 0)] = one.lift(count as u64);
+        }
+        res
+    }
+
+    // Count per timer for every day from 0 to days
+    fn time_series(&self, fishes: &[u64], days: u64) -> Option<Vec<Vec<u128>>> {
+        let timers = self.timers(fishes);
+        let m = self.transition(timers, &1u128);
+        let mut state = self.initial(fishes, &1u128);
+        let mut res = Vec::new();
+        for day in 0..=days {
+            res.push(
+                state
+                    .chunks(self.ages())
+                    .map(|ages| ages.iter().sum())
+                    .collect(),
+            );
+            if day < days {
+                state = m.apply(&state)?;
+            }
+        }
+        Some(res)
+    }
+
+    fn calc_fish_count<T: Count>(&self, fishes: &[u64], days: u64, one: T) -> Option<T> {
+        let m = self.transition(self.timers(fishes), &one).pow(days)?;
+        let state = m.apply(&self.initial(fishes, &one))?;
+        // count all fishes in all day groups
+        let mut sum = one.lift(0);
+        for c in state {
+            sum = sum.add(&c)?;
+        }
+        Some(sum)
+    }
+}
+
+fn write_series<W: Write>(series: &[Vec<u128>], out: &mut W) -> io::Result<()> {
+    let timers = series.first().map_or(0, Vec::len);
+    write!(out, "day")?;
+    for t in 0..timers {
+        write!(out, ",timer{}", t)?;
+    }
+    writeln!(out, ",total")?;
+    for (day, counts) in series.iter().enumerate() {
+        write!(out, "{}", day)?;
+        for c in counts {
+            write!(out, ",{}", c)?;
+        }
+        writeln!(out, ",{}", counts.iter().sum::<u128>())?;
+    }
+    Ok(())
+}
+
+// The exact count grows exponentially, its digits get unmanageable after this
+const MAX_BIG_DAYS: u64 = 100_000;
+
+struct Args {
+    model: LifecycleModel,
+    days: Option<u64>,
+    modulus: Option<u64>,
+    series: Option<String>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut res = Args {
+        model: LifecycleModel::default(),
+        days: None,
+        modulus: None,
+        series: None,
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args.next().ok_or(format!("missing value for {}", arg))?;
+        let number = || value.parse::<u64>().map_err(|e| format!("{}: {}", arg, e));
+        match arg.as_str() {
+            "--days" => res.days = Some(number()?),
+            "--mod" => res.modulus = Some(number()?),
+            "--cycle" => res.model.cycle = number()? as usize,
+            "--delay" => res.model.newborn_delay = number()? as usize,
+            "--mortality" => res.model.mortality = Some(number()? as usize),
+            "--offspring" => res.model.offspring = number()?,
+            "--series" => res.series = Some(value),
+            _ => return Err(format!("unknown argument {}", arg)),
+        }
+    }
+    if res.model.cycle == 0 || res.model.mortality == Some(0) || res.modulus == Some(0) {
+        return Err("cycle, mortality and modulus must be positive".to_string());
+    }
+    Ok(res)
 }
 
 fn main() {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            println!("{}", e);
+            println!("usage: day6 [--days n] [--mod p] [--cycle n] [--delay n] [--mortality n] [--offspring n] [--series file]");
+            return;
+        }
+    };
+    let model = &args.model;
     //let mut fishes = load_input("simple_input.txt").unwrap();
     let fishes = load_input("input.txt").unwrap();
-    println!("day 18 {}", calc_fish_count(&fishes, 18));
-    println!("day 60 {}", calc_fish_count(&fishes, 60));
-    println!("day 256 {}", calc_fish_count(&fishes, 256));
+    for days in [18, 60, 256] {
+        match model.calc_fish_count(&fishes, days, 1u128) {
+            Some(n) => println!("day {} {}", days, n),
+            None => println!("day {} overflows", days),
+        }
+    }
+
+    if let Some(days) = args.days {
+        match args.modulus {
+            Some(p) => println!(
+                "day {} {}",
+                days,
+                model
+                    .calc_fish_count(&fishes, days, ModP::new(1, p))
+                    .unwrap()
+            ),
+            None => match model.calc_fish_count(&fishes, days, 1u128) {
+                Some(n) => println!("day {} {}", days, n),
+                None if days <= MAX_BIG_DAYS => println!(
+                    "day {} {}",
+                    days,
+                    model
+                        .calc_fish_count(&fishes, days, BigUint::from(1u8))
+                        .unwrap()
+                ),
+                None => println!(
+                    "day {} overflows u128 and is too large for exact counting, use --mod p",
+                    days
+                ),
+            },
+        }
+    }
+
+    if let Some(path) = &args.series {
+        let days = args.days.unwrap_or(256);
+        match model.time_series(&fishes, days) {
+            Some(series) => {
+                let mut out = io::BufWriter::new(File::create(path).unwrap());
+                write_series(&series, &mut out).unwrap();
+            }
+            None => println!("time series overflows before day {}", days),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const EXAMPLE: [u64; 5] = [3, 4, 3, 1, 2];
+
+    // Day by day simulation of every single fish as (timer, age)
+    fn simulate(model: &LifecycleModel, fishes: &[u64], days: u64) -> u128 {
+        let mut state: Vec<_> = fishes.iter().map(|f| (*f as usize, 0)).collect();
+        for _ in 0..days {
+            let mut next = Vec::new();
+            for (timer, age) in state {
+                if model.mortality.is_some_and(|m| age + 1 >= m) {
+                    continue;
+                }
+                if timer == 0 {
+                    next.push((model.reset_timer(), age + 1));
+                    for _ in 0..model.offspring {
+                        next.push((model.newborn_timer(), 0));
+                    }
+                } else {
+                    next.push((timer - 1, age + 1));
+                }
+            }
+            state = next;
+        }
+        state.len() as u128
+    }
+
+    #[test]
+    fn test_example() {
+        let model = LifecycleModel::default();
+        assert_eq!(model.calc_fish_count(&EXAMPLE, 18, 1u128), Some(26));
+        assert_eq!(model.calc_fish_count(&EXAMPLE, 80, 1u128), Some(5934));
+        assert_eq!(
+            model.calc_fish_count(&EXAMPLE, 256, 1u128),
+            Some(26984457539)
+        );
+        assert_eq!(
+            model.calc_fish_count(&EXAMPLE, 256, BigUint::from(1u8)),
+            Some(BigUint::from(26984457539u64))
+        );
+        let series = model.time_series(&EXAMPLE, 18).unwrap();
+        assert_eq!(series[18].iter().sum::<u128>(), 26);
+    }
+
+    #[test]
+    fn test_mod_p() {
+        let model = LifecycleModel::default();
+        for p in [2, 7, 1000, 1_000_000_007] {
+            for days in [0, 18, 80, 256] {
+                let exact = model.calc_fish_count(&EXAMPLE, days, 1u128).unwrap();
+                let m = model
+                    .calc_fish_count(&EXAMPLE, days, ModP::new(1, p))
+                    .unwrap();
+                assert_eq!(m.v as u128, exact % p as u128);
+            }
+        }
+    }
+
+    #[test]
+    fn test_mortality_and_offspring() {
+        let model = LifecycleModel {
+            cycle: 4,
+            newborn_delay: 2,
+            mortality: Some(12),
+            offspring: 2,
+        };
+        for days in [0, 1, 7, 11, 12, 13, 30] {
+            assert_eq!(
+                model.calc_fish_count(&EXAMPLE, days, 1u128),
+                Some(simulate(&model, &EXAMPLE, days)),
+                "day {}",
+                days
+            );
+        }
+    }
 }
@@ -1,5 +1,5 @@
 use std::fs::File;
-use std::io::{self, BufRead};
+use std::io::{self, BufRead, Write};
 use std::path::Path;
 
 fn load_input<P>(filename: P) -> io::Result<Vec<i32>>
@@ -14,40 +14,270 @@ where
     Ok(res)
 }
 
-fn fuel_calc<F>(crap_positions: &[i32], func: F) -> (i32, i32)
-where
-    F: Fn(i32, i32) -> i32,
-{
+trait FuelCost {
+    fn cost(&self, crab: usize, from: i32, to: i32) -> i64;
+
+    fn total_fuel(&self, crap_positions: &[i32], target: i32) -> i64 {
+        crap_positions
+            .iter()
+            .enumerate()
+            .map(|(i, x)| self.cost(i, *x, target))
+            .sum()
+    }
+
+    // Optimal (fuel, position), by default the minimum of the cost curve
+    fn align(&self, crap_positions: &[i32]) -> (i64, i32) {
+        cost_curve(crap_positions, self)
+            .into_iter()
+            .map(|(p, f)| (f, p))
+            .min()
+            .unwrap()
+    }
+}
+
+struct Linear;
+struct Triangular;
+struct Quadratic;
+
+struct Weighted<C: FuelCost> {
+    weights: Vec<i64>,
+    model: C,
+}
+
+impl FuelCost for Linear {
+    fn cost(&self, _: usize, from: i32, to: i32) -> i64 {
+        diff(from, to)
+    }
+
+    // Linear cost is minimal at the median
+    fn align(&self, crap_positions: &[i32]) -> (i64, i32) {
+        let mut sorted = crap_positions.to_vec();
+        sorted.sort_unstable();
+        let p = sorted[(sorted.len() - 1) / 2];
+        (self.total_fuel(crap_positions, p), p)
+    }
+}
+
+impl FuelCost for Triangular {
+    fn cost(&self, _: usize, from: i32, to: i32) -> i64 {
+        diff2(from, to)
+    }
+
+    // Triangular cost is minimal within 1/2 of the mean
+    fn align(&self, crap_positions: &[i32]) -> (i64, i32) {
+        align_near_mean(self, crap_positions)
+    }
+}
+
+impl FuelCost for Quadratic {
+    fn cost(&self, _: usize, from: i32, to: i32) -> i64 {
+        diff(from, to).pow(2)
+    }
+
+    // Quadratic cost is minimal at the mean
+    fn align(&self, crap_positions: &[i32]) -> (i64, i32) {
+        align_near_mean(self, crap_positions)
+    }
+}
+
+impl<C: FuelCost> FuelCost for Weighted<C> {
+    fn cost(&self, crab: usize, from: i32, to: i32) -> i64 {
+        self.weights[crab] * self.model.cost(crab, from, to)
+    }
+}
+
+fn align_near_mean<C: FuelCost + ?Sized>(model: &C, crap_positions: &[i32]) -> (i64, i32) {
+    let sum: i64 = crap_positions.iter().map(|x| *x as i64).sum();
+    let floor = sum.div_euclid(crap_positions.len() as i64) as i32;
+    (floor - 1..=floor + 1)
+        .map(|p| (model.total_fuel(crap_positions, p), p))
+        .min()
+        .unwrap()
+}
+
+// Total fuel for every position between the outermost crabs
+fn cost_curve<C: FuelCost + ?Sized>(crap_positions: &[i32], model: &C) -> Vec<(i32, i64)> {
     let max = crap_positions.iter().max().copied().unwrap();
     let min = crap_positions.iter().min().copied().unwrap();
-    let fuels: Vec<_> = (min..=max)
-        .map(|f| crap_positions.iter().map(|x| func(*x, f)).sum())
+    (min..=max)
+        .map(|p| (p, model.total_fuel(crap_positions, p)))
+        .collect()
+}
+
+fn write_cost_curves<W: Write>(
+    crap_positions: &[i32],
+    models: &[(&str, &dyn FuelCost)],
+    out: &mut W,
+) -> io::Result<()> {
+    let curves: Vec<_> = models
+        .iter()
+        .map(|(_, m)| cost_curve(crap_positions, *m))
         .collect();
-    let min: i32 = fuels.iter().min().copied().unwrap();
-    let v = crap_positions[fuels.iter().position(|x| *x == min).unwrap()];
-    (min, v)
+    write!(out, "position")?;
+    for (name, _) in models {
+        write!(out, ",{}", name)?;
+    }
+    writeln!(out)?;
+    for (i, (p, _)) in curves[0].iter().enumerate() {
+        write!(out, "{}", p)?;
+        for c in &curves {
+            write!(out, ",{}", c[i].1)?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
 }
 
-fn diff(x: i32, y: i32) -> i32 {
-    (x - y).abs()
+fn diff(x: i32, y: i32) -> i64 {
+    (x as i64 - y as i64).abs()
 }
 
-fn diff2(x: i32, y: i32) -> i32 {
-    let n = (x - y).abs();
+fn diff2(x: i32, y: i32) -> i64 {
+    let n = diff(x, y);
     // gaussian sum formula
     (n.pow(2) + n) / 2
 }
 
+fn print_alignment(name: &str, model: &dyn FuelCost, pos: &[i32]) {
+    let (min, p) = model.align(pos);
+    println!("{}", name);
+    println!("Fuel needed min {}", min);
+    println!("Pos {}", p);
+}
+
+// One comma separated weight per crab, in the order of the crab positions
+fn load_weights(path: &str) -> Result<Vec<i64>, String> {
+    let contents = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    contents
+        .split([',', '\n'])
+        .filter(|n| !n.trim().is_empty())
+        .map(|n| n.trim().parse::<i64>())
+        .collect::<Result<_, _>>()
+        .map_err(|e| format!("{}: {}", path, e))
+}
+
+struct Args {
+    weights: Option<Vec<i64>>,
+    csv: Option<String>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut res = Args {
+        weights: None,
+        csv: None,
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args.next().ok_or(format!("missing value for {}", arg))?;
+        match arg.as_str() {
+            "--weights" => res.weights = Some(load_weights(&value)?),
+            "--csv" => res.csv = Some(value),
+            _ => return Err(format!("unknown argument {}", arg)),
+        }
+    }
+    Ok(res)
+}
+
 fn main() {
-    println!("Part 1");
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            println!("{}", e);
+            println!("usage: day7 [--weights file] [--csv file]");
+            return;
+        }
+    };
     //let pos = vec![16,1,2,0,4,2,7,1,2,14];
     let pos = load_input("input.txt").unwrap();
-    let (min, p) = fuel_calc(&pos, diff);
-    println!("Fuel needed min {}", min);
-    println!("Pos {}", p);
-    // Part2
-    println!("Part 2");
-    let (min, p) = fuel_calc(&pos, diff2);
-    println!("Fuel needed min {}", min);
-    println!("Pos {}", p);
+    print_alignment("Part 1", &Linear, &pos);
+    print_alignment("Part 2", &Triangular, &pos);
+    print_alignment("Quadratic", &Quadratic, &pos);
+
+    let mut models: Vec<(&str, Box<dyn FuelCost>)> = vec![
+        ("linear", Box::new(Linear)),
+        ("triangular", Box::new(Triangular)),
+        ("quadratic", Box::new(Quadratic)),
+    ];
+    if let Some(weights) = args.weights {
+        if weights.len() != pos.len() {
+            println!(
+                "need one weight per crab, got {} weights for {} crabs",
+                weights.len(),
+                pos.len()
+            );
+            return;
+        }
+        let model = Weighted {
+            weights,
+            model: Triangular,
+        };
+        print_alignment("Weighted triangular", &model, &pos);
+        models.push(("weighted_triangular", Box::new(model)));
+    }
+    if let Some(path) = &args.csv {
+        let models: Vec<_> = models.iter().map(|(n, m)| (*n, m.as_ref())).collect();
+        let res = File::create(path)
+            .and_then(|f| write_cost_curves(&pos, &models, &mut io::BufWriter::new(f)));
+        if let Err(e) = res {
+            println!("{}: {}", path, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const EXAMPLE: [i32; 10] = [16, 1, 2, 0, 4, 2, 7, 1, 2, 14];
+
+    #[test]
+    fn test_example() {
+        assert_eq!(Linear.align(&EXAMPLE), (37, 2));
+        assert_eq!(Triangular.align(&EXAMPLE), (168, 5));
+        // the specialised alignments agree with the full cost curve
+        let curve_min = |m: &dyn FuelCost| {
+            cost_curve(&EXAMPLE, m)
+                .into_iter()
+                .map(|(p, f)| (f, p))
+                .min()
+                .unwrap()
+        };
+        assert_eq!(curve_min(&Linear), (37, 2));
+        assert_eq!(curve_min(&Triangular), (168, 5));
+        assert_eq!(Quadratic.align(&EXAMPLE), curve_min(&Quadratic));
+    }
+
+    #[test]
+    fn test_weighted_and_csv() {
+        let model = Weighted {
+            weights: vec![1; EXAMPLE.len()],
+            model: Triangular,
+        };
+        assert_eq!(
+            cost_curve(&EXAMPLE, &model),
+            cost_curve(&EXAMPLE, &Triangular)
+        );
+        assert_eq!(model.align(&EXAMPLE), Triangular.align(&EXAMPLE));
+        // a heavier crab at 16 moves the optimum towards it
+        let mut weights = vec![1; EXAMPLE.len()];
+        weights[0] = 10;
+        let heavy = Weighted {
+            weights,
+            model: Triangular,
+        };
+        assert!(heavy.align(&EXAMPLE).1 > 5);
+
+        let mut out = Vec::new();
+        write_cost_curves(
+            &EXAMPLE,
+            &[("linear", &Linear), ("weighted", &model)],
+            &mut out,
+        )
+        .unwrap();
+        let csv = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(lines.len(), 1 + 17);
+        assert_eq!(lines[0], "position,linear,weighted");
+        assert_eq!(lines[3], "2,37,206");
+    }
 }
@@ -1,6 +1,259 @@
+use std::fmt;
 use std::fs::File;
 use std::io::{self, BufRead, BufReader, Read};
 use std::path::Path;
+// Segments a to g of the digits 0 to 9
+const DECIMAL: [(char, &str); 10] = [
+    ('0', "abcefg"),
+    ('1', "cf"),
+    ('2', "acdeg"),
+    ('3', "acdfg"),
+    ('4', "bcdf"),
+    ('5', "abdfg"),
+    ('6', "abdefg"),
+    ('7', "acf"),
+    ('8', "abcdefg"),
+    ('9', "abcdfg"),
+];
+
+const HEX_LETTERS: [(char, &str); 6] = [
+    ('A', "abcdef"),
+    ('B', "bdefg"),
+    ('C', "abeg"),
+    ('D', "cdefg"),
+    ('E', "abdeg"),
+    ('F', "abde"),
+];
+
+// Sixteen segments: a/b top halves, c/d right upper and lower, e/f bottom
+// halves (right, left), g/h left lower and upper, i/j middle halves,
+// k/m upper diagonals, l/o center verticals, n/p lower diagonals
+const HEX_SIXTEEN: [(char, &str); 16] = [
+    ('0', "abcdefghkp"),
+    ('1', "bcd"),
+    ('2', "abefmn"),
+    ('3', "abcdefj"),
+    ('4', "cdhij"),
+    ('5', "abefhip"),
+    ('6', "abdefghij"),
+    ('7', "abmo"),
+    ('8', "abcdefghij"),
+    ('9', "abcdefhij"),
+    ('A', "abcdghij"),
+    ('B', "abcdefjo"),
+    ('C', "abefgh"),
+    ('D', "bcdelo"),
+    ('E', "abefghi"),
+    ('F', "abghi"),
+];
+
+// Symbols of a display, the value of a symbol is its index and the
+// number of symbols is the base
+struct GlyphTable {
+    segments: usize,
+    symbols: Vec<char>,
+    glyphs: Vec<u32>,
+}
+
+impl GlyphTable {
+    fn new(segments: usize, table: &[(char, &str)]) -> Result<Self, DecodeError> {
+        let glyphs: Vec<_> = table
+            .iter()
+            .map(|(_, g)| to_bitset(g, segments))
+            .collect::<Result<_, _>>()?;
+        // a glyph shown for two symbols couldn't be decoded
+        for (i, g) in glyphs.iter().enumerate() {
+            if glyphs[..i].contains(g) {
+                return Err(DecodeError::InvalidPattern(table[i].1.to_string()));
+            }
+        }
+        Ok(Self {
+            segments,
+            symbols: table.iter().map(|(c, _)| *c).collect(),
+            glyphs,
+        })
+    }
+
+    fn decimal() -> Self {
+        Self::new(7, &DECIMAL).unwrap()
+    }
+
+    fn hexadecimal() -> Self {
+        let table: Vec<_> = DECIMAL.iter().chain(HEX_LETTERS.iter()).copied().collect();
+        Self::new(7, &table).unwrap()
+    }
+
+    fn sixteen_segment() -> Self {
+        Self::new(16, &HEX_SIXTEEN).unwrap()
+    }
+
+    // One "symbol segments" pair per line like "A abcdef"
+    fn load<P: AsRef<Path>>(filename: P) -> io::Result<Result<Self, DecodeError>> {
+        let contents = read_file(filename)?;
+        let table: Vec<_> = contents
+            .lines()
+            .filter_map(|l| {
+                let mut it = l.split_whitespace();
+                Some((it.next()?.chars().next()?, it.next().unwrap_or("")))
+            })
+            .collect();
+        let segments = table
+            .iter()
+            .flat_map(|(_, g)| g.bytes())
+            .map(|c| c.wrapping_sub(b'a') as usize + 1)
+            .max()
+            .unwrap_or(0);
+        Ok(Self::new(segments, &table))
+    }
+
+    fn full(&self) -> u32 {
+        (1 << self.segments) - 1
+    }
+
+    fn base(&self) -> usize {
+        self.glyphs.len()
+    }
+
+    fn value(&self, glyph: u32) -> usize {
+        self.glyphs.iter().position(|g| *g == glyph).unwrap()
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum DecodeError {
+    InvalidPattern(String),
+    // no wire mapping fits all patterns
+    Inconsistent,
+    // more than one wire mapping fits all patterns
+    Ambiguous,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::InvalidPattern(p) => write!(f, "invalid pattern {}", p),
+            DecodeError::Inconsistent => write!(f, "no wire mapping fits the patterns"),
+            DecodeError::Ambiguous => write!(f, "several wire mappings fit the patterns"),
+        }
+    }
+}
+
+fn to_bitset(pattern: &str, segments: usize) -> Result<u32, DecodeError> {
+    pattern.bytes().try_fold(0, |set, c| {
+        let wire = c.wrapping_sub(b'a') as usize;
+        if wire >= segments.min(32) || set & (1 << wire) != 0 {
+            Err(DecodeError::InvalidPattern(pattern.to_string()))
+        } else {
+            Ok(set | 1 << wire)
+        }
+    })
+}
+
+// Constraint solver for the wire to segment mapping, domains[w] holds the
+// segments wire w can still be connected to
+struct WireSolver<'a> {
+    patterns: &'a [u32],
+    glyphs: &'a [u32],
+    full: u32,
+}
+
+impl WireSolver<'_> {
+    fn propagate(&self, domains: &mut [u32]) -> bool {
+        loop {
+            let before = domains.to_vec();
+            for p in self.patterns {
+                // glyphs the pattern can still show
+                let mut inside = 0;
+                let mut outside = 0;
+                for g in self.glyphs {
+                    let fits = g.count_ones() == p.count_ones()
+                        && domains.iter().enumerate().all(|(w, d)| {
+                            if p & (1 << w) != 0 {
+                                d & g != 0
+                            } else {
+                                d & !g & self.full != 0
+                            }
+                        });
+                    if fits {
+                        inside |= g;
+                        outside |= !g & self.full;
+                    }
+                }
+                for (w, d) in domains.iter_mut().enumerate() {
+                    *d &= if p & (1 << w) != 0 { inside } else { outside };
+                }
+            }
+            // a segment used by a fixed wire can't be used by others
+            for w in 0..domains.len() {
+                if domains[w].count_ones() == 1 {
+                    let fixed = domains[w];
+                    for (o, d) in domains.iter_mut().enumerate() {
+                        if o != w {
+                            *d &= !fixed;
+                        }
+                    }
+                }
+            }
+            if domains.contains(&0) {
+                return false;
+            }
+            if domains == before.as_slice() {
+                return true;
+            }
+        }
+    }
+
+    // Collects up to limit complete mappings
+    fn search(&self, mut domains: Vec<u32>, found: &mut Vec<Vec<u32>>, limit: usize) {
+        if found.len() >= limit || !self.propagate(&mut domains) {
+            return;
+        }
+        match (0..domains.len())
+            .filter(|w| domains[*w].count_ones() > 1)
+            .min_by_key(|w| domains[*w].count_ones())
+        {
+            None => {
+                if self
+                    .patterns
+                    .iter()
+                    .all(|p| self.glyphs.contains(&map_bitset(&domains, *p)))
+                {
+                    found.push(domains);
+                }
+            }
+            Some(w) => {
+                let mut rest = domains[w];
+                while rest != 0 {
+                    let s = rest & rest.wrapping_neg();
+                    rest &= !s;
+                    let mut next = domains.clone();
+                    next[w] = s;
+                    self.search(next, found, limit);
+                }
+            }
+        }
+    }
+
+    fn solve(&self, wires: usize) -> Result<Vec<u32>, DecodeError> {
+        let mut found = Vec::new();
+        self.search(vec![self.full; wires], &mut found, 2);
+        match found.len() {
+            0 => Err(DecodeError::Inconsistent),
+            1 => Ok(found.pop().unwrap()),
+            _ => Err(DecodeError::Ambiguous),
+        }
+    }
+}
+
+// Translates a wire bitset to a segment bitset
+fn map_bitset(mapping: &[u32], wires: u32) -> u32 {
+    mapping
+        .iter()
+        .enumerate()
+        .filter(|(w, _)| wires & (1 << w) != 0)
+        .fold(0, |set, (_, s)| set | s)
+}
+
 struct SegmentDecoder {
     patterns: Vec<String>,
     display: Vec<String>,
@@ -14,61 +267,108 @@ impl SegmentDecoder {
             .count()
     }
 
-    fn get_bitset(&self, n: usize) -> u8 {
-        self.patterns
+    // Segment bitset for every wire
+    fn wire_mapping(&self, table: &GlyphTable) -> Result<Vec<u32>, DecodeError> {
+        let patterns = self
+            .patterns
             .iter()
-            .find(|p| p.len() == n)
-            .unwrap()
-            .chars()
-            .map(|c| (1 << (c as u8 - b'a')))
-            .sum()
+            .chain(self.display.iter())
+            .map(|p| to_bitset(p, table.segments))
+            .collect::<Result<Vec<_>, _>>()?;
+        let solver = WireSolver {
+            patterns: &patterns,
+            glyphs: &table.glyphs,
+            full: table.full(),
+        };
+        solver.solve(table.segments)
     }
 
-    fn get_bitsets(&self, n: usize) -> Vec<u8> {
-        self.patterns
+    // Segment bitsets shown on the display
+    fn decoded_segments(&self, table: &GlyphTable) -> Result<Vec<u32>, DecodeError> {
+        let mapping = self.wire_mapping(table)?;
+        self.display
             .iter()
-            .filter(|p| p.len() == n)
-            .map(|p| p.chars().map(|c| (1 << (c as u8 - b'a'))).sum())
+            .map(|d| Ok(map_bitset(&mapping, to_bitset(d, table.segments)?)))
             .collect()
     }
 
-    fn calc_number(&self) -> usize {
-        // Find all numbers via logic like subset of and so one
-        // This ones are obvious because of the length
-        let one = self.get_bitset(2);
-        let seven = self.get_bitset(3);
-        let four = self.get_bitset(4);
-        let eight = self.get_bitset(7);
-        // the others can be determine via masking
-        let digts5 = self.get_bitsets(5);
-        let three = *digts5.iter().find(|x| (one & **x) == one).unwrap();
-        assert!(digts5.len() == 3);
-
-        let digts6 = self.get_bitsets(6);
-        assert!(digts6.len() == 3);
-        let six = *digts6.iter().find(|x| (one & **x) != one).unwrap();
-
-        let c = six ^ eight;
-        let two = *digts5
-            .iter()
-            .find(|x| **x & c == c && **x != three)
-            .unwrap();
-        let nine = *digts6.iter().find(|x| (four & **x) == four).unwrap();
-        let zero = *digts6.iter().find(|x| **x != nine && **x != six).unwrap();
-        let five = *digts5.iter().find(|x| **x != two && **x != three).unwrap();
-        let digits: Vec<u8> = vec![zero, one, two, three, four, five, six, seven, eight, nine];
-        let cnt = self.display.len();
+    fn calc_number(&self, table: &GlyphTable) -> Result<usize, DecodeError> {
+        let segments = self.decoded_segments(table)?;
+        let cnt = segments.len();
         // Generate the digits for the result
-        self.display
+        Ok(segments
             .iter()
             .enumerate()
-            .map(|(i, d)| {
-                let s: u8 = d.chars().map(|c| (1 << (c as u8 - b'a'))).sum();
-                let p = digits.iter().position(|p| *p == s).unwrap();
-                p * 10usize.pow((cnt - i - 1) as u32)
-            })
-            .sum()
+            .map(|(i, s)| table.value(*s) * table.base().pow((cnt - i - 1) as u32))
+            .sum())
     }
+
+    // Decoded display in the base of the glyph table
+    fn display_symbols(&self, table: &GlyphTable) -> Result<String, DecodeError> {
+        Ok(self
+            .decoded_segments(table)?
+            .iter()
+            .map(|s| table.symbols[table.value(*s)])
+            .collect())
+    }
+}
+
+// Wiring like "fcbdgae" where the n-th letter is the segment of wire n
+fn parse_wiring(wiring: &str, segments: usize) -> Result<Vec<usize>, DecodeError> {
+    let set = to_bitset(wiring, segments)?;
+    if set.count_ones() as usize != segments || wiring.len() != segments {
+        return Err(DecodeError::InvalidPattern(wiring.to_string()));
+    }
+    Ok(wiring.bytes().map(|c| (c - b'a') as usize).collect())
+}
+
+fn scramble(segments: u32, wiring: &[usize]) -> String {
+    wiring
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| segments & (1 << **s) != 0)
+        .map(|(w, _)| (b'a' + w as u8) as char)
+        .collect()
+}
+
+// Puzzle line with all scrambled glyphs and the output symbols
+fn encode(table: &GlyphTable, output: &str, wiring: &[usize]) -> Result<String, DecodeError> {
+    let patterns: Vec<_> = table.glyphs.iter().map(|g| scramble(*g, wiring)).collect();
+    let display = output
+        .chars()
+        .map(|c| match table.symbols.iter().position(|s| *s == c) {
+            Some(v) => Ok(scramble(table.glyphs[v], wiring)),
+            None => Err(DecodeError::InvalidPattern(output.to_string())),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(format!("{} | {}", patterns.join(" "), display.join(" ")))
+}
+
+// Classic three line seven segment glyphs
+fn render_segments(glyphs: &[u32]) -> String {
+    let seg = |g: u32, s: usize, c: char| if g & (1 << s) != 0 { c } else { ' ' };
+    let mut rows = vec![String::new(); 3];
+    for g in glyphs {
+        rows[0].extend([' ', seg(*g, 0, '_'), ' ']);
+        rows[1].extend([seg(*g, 1, '|'), seg(*g, 3, '_'), seg(*g, 2, '|')]);
+        rows[2].extend([seg(*g, 4, '|'), seg(*g, 6, '_'), seg(*g, 5, '|')]);
+    }
+    rows.join("\n")
+}
+
+fn format_mapping(mapping: &[u32]) -> String {
+    mapping
+        .iter()
+        .enumerate()
+        .map(|(w, s)| {
+            format!(
+                "{}->{}",
+                (b'a' + w as u8) as char,
+                (b'a' + s.trailing_zeros() as u8) as char
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
 }
 
 fn load_file<P>(filename: P) -> io::Result<Vec<SegmentDecoder>>
@@ -100,19 +400,174 @@ where
         })
         .collect())
 }
-pub fn read_file(filepath: &str) -> String {
-    let file = File::open(filepath).expect("Unable to open file");
+pub fn read_file<P: AsRef<Path>>(filepath: P) -> io::Result<String> {
+    let file = File::open(filepath)?;
     let mut buffered_reader = BufReader::new(file);
     let mut contents = String::new();
-    buffered_reader
-        .read_to_string(&mut contents)
-        .expect("Unable to read file into the string");
-    contents.trim_end().to_string()
+    buffered_reader.read_to_string(&mut contents)?;
+    Ok(contents.trim_end().to_string())
+}
+struct Args {
+    table: GlyphTable,
+    render: bool,
+    // symbols and wiring
+    encode: Option<(String, String)>,
 }
+
+fn parse_args() -> Result<Args, String> {
+    let mut res = Args {
+        table: GlyphTable::decimal(),
+        render: false,
+        encode: None,
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("missing value for {}", arg));
+        match arg.as_str() {
+            "--glyphs" => {
+                res.table = match value()?.as_str() {
+                    "decimal" => GlyphTable::decimal(),
+                    "hex" => GlyphTable::hexadecimal(),
+                    "hex16" => GlyphTable::sixteen_segment(),
+                    file => GlyphTable::load(file)
+                        .map_err(|e| format!("{}: {}", file, e))?
+                        .map_err(|e| format!("{}: {}", file, e))?,
+                }
+            }
+            "--render" => res.render = true,
+            "--encode" => {
+                let symbols = value()?;
+                res.encode = Some((symbols, value()?));
+            }
+            _ => return Err(format!("unknown argument {}", arg)),
+        }
+    }
+    Ok(res)
+}
+
 fn main() {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            println!("{}", e);
+            println!("usage: day8 [--glyphs decimal|hex|hex16|file] [--render] [--encode symbols wiring]");
+            return;
+        }
+    };
+    let table = &args.table;
     let inp = load_file("input.txt").unwrap();
     let sum: usize = inp.iter().map(|m| m.display_contains_guessable()).sum();
     println!("No digits 1, 4, 7, or 8: {}", sum);
-    let total: usize = inp.iter().map(SegmentDecoder::calc_number).sum();
+    let mut total = 0;
+    for (i, m) in inp.iter().enumerate() {
+        match m.calc_number(table) {
+            Ok(n) => total += n,
+            Err(e) => println!("Line {}: {}", i + 1, e),
+        }
+    }
     println!("Total {}", total);
+    if let Some(m) = inp.first() {
+        if let Ok(mapping) = m.wire_mapping(table) {
+            println!("Wires of line 1: {}", format_mapping(&mapping));
+        }
+    }
+
+    if args.render {
+        for m in &inp {
+            match m.decoded_segments(table) {
+                Ok(segments) if table.segments == 7 => println!("{}", render_segments(&segments)),
+                Ok(_) => println!("{}", m.display_symbols(table).unwrap()),
+                Err(_) => {}
+            }
+        }
+    }
+    if let Some((symbols, wiring)) = &args.encode {
+        match parse_wiring(wiring, table.segments)
+            .and_then(|wiring| encode(table, symbols, &wiring))
+        {
+            Ok(line) => println!("{}", line),
+            Err(e) => println!("{}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn decoder(line: &str) -> SegmentDecoder {
+        let mut it = line.split(" | ");
+        let mut words = || it.next().unwrap().split(' ').map(str::to_string).collect();
+        SegmentDecoder {
+            patterns: words(),
+            display: words(),
+        }
+    }
+
+    #[test]
+    fn test_decode() {
+        let d = decoder(
+            "acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb eafb cagedb ab | cdfeb fcadb cdfeb cdbaf",
+        );
+        assert_eq!(d.calc_number(&GlyphTable::decimal()), Ok(5353));
+        let mapping: Vec<_> = d.wire_mapping(&GlyphTable::decimal()).unwrap();
+        assert_eq!(
+            format_mapping(&mapping),
+            "a->c b->f c->g d->a e->b f->d g->e"
+        );
+    }
+
+    #[test]
+    fn test_encode_roundtrip() {
+        let table = GlyphTable::decimal();
+        let wiring = parse_wiring("fcbdgae", 7).unwrap();
+        let d = decoder(&encode(&table, "9042", &wiring).unwrap());
+        assert_eq!(d.calc_number(&GlyphTable::decimal()), Ok(9042));
+        assert_eq!(
+            format_mapping(&d.wire_mapping(&GlyphTable::decimal()).unwrap()),
+            "a->f b->c c->b d->d e->g f->a g->e"
+        );
+        assert_eq!(
+            render_segments(&d.decoded_segments(&GlyphTable::decimal()).unwrap()),
+            " _  _     _ \n|_|| ||_| _|\n _||_|  ||_ "
+        );
+    }
+
+    #[test]
+    fn test_other_bases() {
+        let table = GlyphTable::hexadecimal();
+        let wiring = parse_wiring("fcbdgae", 7).unwrap();
+        let d = decoder(&encode(&table, "C0DE", &wiring).unwrap());
+        assert_eq!(d.calc_number(&table), Ok(0xC0DE));
+        assert_eq!(d.display_symbols(&table), Ok("C0DE".to_string()));
+
+        let table = GlyphTable::sixteen_segment();
+        let wiring = parse_wiring("pobnmclkdjiahgfe", 16).unwrap();
+        let d = decoder(&encode(&table, "BEEF", &wiring).unwrap());
+        assert_eq!(d.calc_number(&table), Ok(0xBEEF));
+        assert_eq!(
+            format_mapping(&d.wire_mapping(&table).unwrap()),
+            "a->p b->o c->b d->n e->m f->c g->l h->k i->d j->j k->i l->a m->h n->g o->f p->e"
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            decoder("ab | ab").calc_number(&GlyphTable::decimal()),
+            Err(DecodeError::Ambiguous)
+        );
+        assert_eq!(
+            decoder("ab abc abd | ab").calc_number(&GlyphTable::decimal()),
+            Err(DecodeError::Inconsistent)
+        );
+        assert_eq!(
+            decoder("ab xy | ab").calc_number(&GlyphTable::decimal()),
+            Err(DecodeError::InvalidPattern("xy".to_string()))
+        );
+        assert_eq!(
+            GlyphTable::new(3, &[('0', "ab"), ('1', "c"), ('2', "ba")]).err(),
+            Some(DecodeError::InvalidPattern("ba".to_string()))
+        );
+    }
 }
@@ -1,20 +1,97 @@
-use std::collections::HashSet;
+mod render;
+
 use std::fs::File;
 use std::io::{self, BufRead};
 use std::path::Path;
 
-fn find_low_points(d: &[Vec<u32>]) -> Vec<(usize, usize)> {
-    let x_max = d[0].len();
-    let y_max = d.len();
+#[derive(Clone, Copy, PartialEq)]
+enum Connectivity {
+    Four,
+    Eight,
+}
+
+const FOUR: [(isize, isize); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+const EIGHT: [(isize, isize); 8] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 0),
+    (1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+
+struct Terrain {
+    // cells with at least this height separate the basins
+    wall: u32,
+    connectivity: Connectivity,
+    // equal neighbours form a plateau which can be a low point as a whole
+    plateaus: bool,
+}
+
+impl Default for Terrain {
+    fn default() -> Self {
+        Self {
+            wall: 9,
+            connectivity: Connectivity::Four,
+            plateaus: false,
+        }
+    }
+}
+
+impl Terrain {
+    fn is_wall(&self, height: u32) -> bool {
+        height >= self.wall
+    }
+
+    fn neighbours<'a>(
+        &self,
+        d: &'a [Vec<u32>],
+        x: usize,
+        y: usize,
+    ) -> impl Iterator<Item = (usize, usize)> + 'a {
+        let offsets: &'static [(isize, isize)] = match self.connectivity {
+            Connectivity::Four => &FOUR,
+            Connectivity::Eight => &EIGHT,
+        };
+        offsets.iter().filter_map(move |(dx, dy)| {
+            let (nx, ny) = (x.checked_add_signed(*dx)?, y.checked_add_signed(*dy)?);
+            (ny < d.len() && nx < d[ny].len()).then_some((nx, ny))
+        })
+    }
+}
+
+fn at_border(d: &[Vec<u32>], x: usize, y: usize) -> bool {
+    x == 0 || y == 0 || y == d.len() - 1 || x == d[y].len() - 1
+}
+
+// A plateau low point is reported once with its first cell
+fn find_low_points(d: &[Vec<u32>], terrain: &Terrain) -> Vec<(usize, usize)> {
+    let mut seen = vec![vec![false; d[0].len()]; d.len()];
     let mut lows = Vec::new();
-    for y in 0..y_max {
-        for x in 0..x_max {
+    let mut stack = Vec::new();
+    for y in 0..d.len() {
+        for x in 0..d[y].len() {
             let p = d[y][x];
-            if (x == 0 || d[y][x - 1] > p)
-                && (y == 0 || d[y - 1][x] > p)
-                && (x >= x_max - 1 || d[y][x + 1] > p)
-                && (y >= y_max - 1 || d[y + 1][x] > p)
-            {
+            if terrain.is_wall(p) || seen[y][x] {
+                continue;
+            }
+            let mut low = true;
+            seen[y][x] = true;
+            stack.push((x, y));
+            while let Some((x, y)) = stack.pop() {
+                for (nx, ny) in terrain.neighbours(d, x, y) {
+                    let h = d[ny][nx];
+                    if h < p || (h == p && !terrain.plateaus) {
+                        low = false;
+                    } else if h == p && !seen[ny][nx] {
+                        seen[ny][nx] = true;
+                        stack.push((nx, ny));
+                    }
+                }
+            }
+            if low {
                 lows.push((x, y));
             }
         }
@@ -22,37 +99,115 @@ fn find_low_points(d: &[Vec<u32>]) -> Vec<(usize, usize)> {
     lows
 }
 
-fn calc_basin(d: &[Vec<u32>], x: usize, y: usize, visited: &mut HashSet<(usize, usize)>) -> usize {
-    visited.insert((x, y));
-    let mut cnt = 0;
-    let p = d[y][x];
-    // height 9 doesn't count as basin
-    if p != 9 {
-        if x != 0 && !visited.contains(&(x - 1, y)) && d[y][x - 1] > p {
-            cnt += calc_basin(d, x - 1, y, visited);
-        }
-        if y != 0 && !visited.contains(&(x, y - 1)) && d[y - 1][x] > p {
-            cnt += calc_basin(d, x, y - 1, visited);
+struct Basins {
+    // basin id of every cell, None for walls
+    labels: Vec<Vec<Option<usize>>>,
+    sizes: Vec<usize>,
+}
+
+// Labels all basins with an iterative flood fill, walls don't count as basin
+fn label_basins(d: &[Vec<u32>], terrain: &Terrain) -> Basins {
+    let mut labels = vec![vec![None; d[0].len()]; d.len()];
+    let mut sizes = Vec::new();
+    let mut stack = Vec::new();
+    for y in 0..d.len() {
+        for x in 0..d[y].len() {
+            if terrain.is_wall(d[y][x]) || labels[y][x].is_some() {
+                continue;
+            }
+            let id = sizes.len();
+            let mut size = 0;
+            labels[y][x] = Some(id);
+            stack.push((x, y));
+            while let Some((x, y)) = stack.pop() {
+                size += 1;
+                for (nx, ny) in terrain.neighbours(d, x, y) {
+                    if !terrain.is_wall(d[ny][nx]) && labels[ny][nx].is_none() {
+                        labels[ny][nx] = Some(id);
+                        stack.push((nx, ny));
+                    }
+                }
+            }
+            sizes.push(size);
         }
-        if x + 1 < d[y].len() && !visited.contains(&(x + 1, y)) {
-            cnt += calc_basin(d, x + 1, y, visited);
+    }
+    Basins { labels, sizes }
+}
+
+impl Basins {
+    fn basin_of(&self, x: usize, y: usize) -> Option<usize> {
+        self.labels[y][x]
+    }
+
+    // The puzzle expects every basin to drain into exactly one low point
+    fn count_irregular(&self, lows: &[(usize, usize)]) -> usize {
+        let mut drains = vec![0; self.sizes.len()];
+        for (x, y) in lows {
+            if let Some(id) = self.basin_of(*x, *y) {
+                drains[id] += 1;
+            }
         }
-        if y + 1 < d.len() && !visited.contains(&(x, y + 1)) && d[y + 1][x] > p {
-            cnt += calc_basin(d, x, y + 1, visited);
+        drains.iter().filter(|n| **n != 1).count()
+    }
+}
+
+struct BasinInfo {
+    id: usize,
+    // lowest cell of the basin, the first one found for equal heights, with
+    // plateaus or several low points it isn't always one of find_low_points
+    lowest: (usize, usize),
+    size: usize,
+    // (min x, min y, max x, max y)
+    bbox: (usize, usize, usize, usize),
+    max_height: u32,
+    // cells with a neighbour outside the basin or at the map border
+    perimeter: Vec<(usize, usize)>,
+}
+
+fn basin_records(d: &[Vec<u32>], basins: &Basins, terrain: &Terrain) -> Vec<BasinInfo> {
+    let mut res: Vec<BasinInfo> = Vec::with_capacity(basins.sizes.len());
+    for y in 0..d.len() {
+        for x in 0..d[y].len() {
+            let id = match basins.basin_of(x, y) {
+                Some(id) => id,
+                None => continue,
+            };
+            let h = d[y][x];
+            if id == res.len() {
+                res.push(BasinInfo {
+                    id,
+                    lowest: (x, y),
+                    size: 0,
+                    bbox: (x, y, x, y),
+                    max_height: h,
+                    perimeter: Vec::new(),
+                });
+            }
+            let info = &mut res[id];
+            info.size += 1;
+            if h < d[info.lowest.1][info.lowest.0] {
+                info.lowest = (x, y);
+            }
+            info.max_height = info.max_height.max(h);
+            let (x0, y0, x1, y1) = info.bbox;
+            info.bbox = (x0.min(x), y0.min(y), x1.max(x), y1.max(y));
+            if at_border(d, x, y)
+                || terrain
+                    .neighbours(d, x, y)
+                    .any(|(nx, ny)| basins.basin_of(nx, ny) != Some(id))
+            {
+                info.perimeter.push((x, y));
+            }
         }
-        cnt + 1
-    } else {
-        0
     }
+    res
 }
 
-fn find_largest_basins(d: &[Vec<u32>], lows: &[(usize, usize)]) -> Vec<usize> {
-    let mut basins: Vec<_> = lows
-        .iter()
-        .map(|(x, y)| calc_basin(d, *x, *y, &mut HashSet::new()))
-        .collect();
-    basins.sort_unstable();
-    basins
+// Basin records sorted by size, largest first
+fn find_largest_basins(d: &[Vec<u32>], basins: &Basins, terrain: &Terrain) -> Vec<BasinInfo> {
+    let mut res = basin_records(d, basins, terrain);
+    res.sort_by_key(|b| std::cmp::Reverse(b.size));
+    res
 }
 
 fn load_input<P>(filename: P) -> io::Result<Vec<Vec<u32>>>
@@ -71,15 +226,176 @@ where
         .collect())
 }
 
+struct Args {
+    terrain: Terrain,
+    ppm: Option<String>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut res = Args {
+        terrain: Terrain::default(),
+        ppm: None,
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--diagonal" => res.terrain.connectivity = Connectivity::Eight,
+            "--plateaus" => res.terrain.plateaus = true,
+            "--wall" | "--ppm" => {
+                let value = args.next().ok_or(format!("missing value for {}", arg))?;
+                if arg == "--ppm" {
+                    res.ppm = Some(value);
+                } else {
+                    res.terrain.wall = value.parse().map_err(|e| format!("{}: {}", arg, e))?;
+                }
+            }
+            _ => return Err(format!("unknown argument {}", arg)),
+        }
+    }
+    Ok(res)
+}
+
 fn main() {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            println!("{}", e);
+            println!("usage: day9 [--wall n] [--diagonal] [--plateaus] [--ppm file]");
+            return;
+        }
+    };
+    let terrain = &args.terrain;
     let d = load_input("input.txt").unwrap();
-    let lows = find_low_points(&d);
+    let lows = find_low_points(&d, terrain);
     let cnt: u32 = lows.iter().map(|(x, y)| d[*y][*x] + 1).sum();
     println!("Numer of lows {}", cnt);
-    let danger_count = find_largest_basins(&d, &lows)
-        .iter()
-        .rev()
-        .take(3)
-        .fold(1, |c, i| i * c);
+    let basins = label_basins(&d, terrain);
+    println!(
+        "Number of basins {} ({} without a single low point)",
+        basins.sizes.len(),
+        basins.count_irregular(&lows)
+    );
+    let largest = find_largest_basins(&d, &basins, terrain);
+    for b in largest.iter().take(3) {
+        println!(
+            "basin {} lowest {:?} size {} bbox {:?} max height {} perimeter {}",
+            b.id,
+            b.lowest,
+            b.size,
+            b.bbox,
+            b.max_height,
+            b.perimeter.len()
+        );
+    }
+    let danger_count: usize = largest.iter().take(3).map(|b| b.size).product();
     println!("danger_count = {}", danger_count);
+
+    if let Some(path) = &args.ppm {
+        let mut out = io::BufWriter::new(File::create(path).unwrap());
+        render::write_ppm(&d, &basins, &mut out).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn grid(rows: &[&str]) -> Vec<Vec<u32>> {
+        rows.iter()
+            .map(|l| l.chars().map(|c| c.to_digit(10).unwrap()).collect())
+            .collect()
+    }
+
+    fn example() -> Vec<Vec<u32>> {
+        grid(&[
+            "2199943210",
+            "3987894921",
+            "9856789892",
+            "8767896789",
+            "9899965678",
+        ])
+    }
+
+    #[test]
+    fn test_example() {
+        let d = example();
+        let terrain = Terrain::default();
+        let lows = find_low_points(&d, &terrain);
+        assert_eq!(lows, vec![(1, 0), (9, 0), (2, 2), (6, 4)]);
+        let basins = label_basins(&d, &terrain);
+        assert_eq!(basins.sizes, vec![3, 9, 14, 9]);
+        assert_eq!(basins.count_irregular(&lows), 0);
+        let danger: usize = find_largest_basins(&d, &basins, &terrain)
+            .iter()
+            .take(3)
+            .map(|b| b.size)
+            .product();
+        assert_eq!(danger, 1134);
+    }
+
+    #[test]
+    fn test_equal_heights() {
+        // the old recursion only walked uphill but to the right, so it missed the 1 below
+        let d = grid(&["011", "919"]);
+        let terrain = Terrain::default();
+        assert_eq!(find_low_points(&d, &terrain), vec![(0, 0)]);
+        let basins = label_basins(&d, &terrain);
+        assert_eq!(basins.sizes, vec![4]);
+        assert_eq!(basins.basin_of(1, 1), Some(0));
+        assert_eq!(basins.basin_of(0, 1), None);
+    }
+
+    #[test]
+    fn test_records() {
+        let d = example();
+        let terrain = Terrain::default();
+        let basins = label_basins(&d, &terrain);
+        let records = basin_records(&d, &basins, &terrain);
+        let top_left = &records[0];
+        assert_eq!(top_left.size, 3);
+        assert_eq!(top_left.lowest, (1, 0));
+        assert_eq!(top_left.bbox, (0, 0, 1, 1));
+        assert_eq!(top_left.perimeter, vec![(0, 0), (1, 0), (0, 1)]);
+        let middle = &records[2];
+        assert_eq!(middle.size, 14);
+        assert_eq!(middle.lowest, (2, 2));
+        assert_eq!(middle.bbox, (0, 1, 5, 4));
+        assert_eq!(middle.max_height, 8);
+        // only (2, 2), (3, 2), (4, 2) and (1, 3) are surrounded by the basin
+        assert_eq!(middle.perimeter.len(), 10);
+        assert!(!middle.perimeter.contains(&(2, 2)));
+        let largest = find_largest_basins(&d, &basins, &terrain);
+        assert_eq!(largest[0].id, 2);
+        assert_eq!(largest[0].size, 14);
+    }
+
+    #[test]
+    fn test_plateaus() {
+        let d = grid(&["3223", "4444"]);
+        let mut terrain = Terrain::default();
+        assert!(find_low_points(&d, &terrain).is_empty());
+        terrain.plateaus = true;
+        // the 2s are one low point, the 4s drain into them
+        assert_eq!(find_low_points(&d, &terrain), vec![(1, 0)]);
+    }
+
+    #[test]
+    fn test_connectivity_and_wall() {
+        let d = grid(&["191", "919", "191"]);
+        let mut terrain = Terrain::default();
+        assert_eq!(find_low_points(&d, &terrain).len(), 5);
+        assert_eq!(label_basins(&d, &terrain).sizes, vec![1; 5]);
+        terrain.connectivity = Connectivity::Eight;
+        assert_eq!(label_basins(&d, &terrain).sizes, vec![5]);
+        assert!(find_low_points(&d, &terrain).is_empty());
+        terrain.plateaus = true;
+        assert_eq!(find_low_points(&d, &terrain), vec![(0, 0)]);
+
+        let d = grid(&["1781"]);
+        let mut terrain = Terrain::default();
+        assert_eq!(label_basins(&d, &terrain).sizes, vec![4]);
+        terrain.wall = 8;
+        assert_eq!(label_basins(&d, &terrain).sizes, vec![2, 1]);
+        assert_eq!(find_low_points(&d, &terrain), vec![(0, 0), (3, 0)]);
+    }
 }
@@ -0,0 +1,34 @@
+use crate::Basins;
+use std::io::{self, Write};
+
+// Spreads the basin ids around the color wheel so neighbouring basins differ
+fn basin_color(id: usize) -> [f64; 3] {
+    let hue = (id as f64 * 0.618_033_988_75).fract() * 6.0;
+    let x = 1.0 - (hue % 2.0 - 1.0).abs();
+    match hue as usize {
+        0 => [1.0, x, 0.0],
+        1 => [x, 1.0, 0.0],
+        2 => [0.0, 1.0, x],
+        3 => [0.0, x, 1.0],
+        4 => [x, 0.0, 1.0],
+        _ => [1.0, 0.0, x],
+    }
+}
+
+// Heightmap as PPM, basins colour coded and darker the deeper they are, walls in gray
+pub fn write_ppm<W: Write>(d: &[Vec<u32>], basins: &Basins, out: &mut W) -> io::Result<()> {
+    let (w, h) = (d[0].len(), d.len());
+    let max_height = d.iter().flatten().copied().max().unwrap_or(0).max(1);
+    let mut img = Vec::with_capacity(w * h * 3);
+    for (y, row) in d.iter().enumerate() {
+        for (x, height) in row.iter().enumerate() {
+            let light = 0.3 + 0.7 * *height as f64 / max_height as f64;
+            match basins.basin_of(x, y) {
+                Some(id) => img.extend(basin_color(id).iter().map(|c| (c * light * 255.0) as u8)),
+                None => img.extend([(light * 96.0) as u8; 3]),
+            }
+        }
+    }
+    write!(out, "P6\n{} {}\n255\n", w, h)?;
+    out.write_all(&img)
+}